@@ -1,21 +1,99 @@
 use std::io;
+use std::error;
 use std::convert::From;
+use std::path::{Path, PathBuf};
 use std::fmt::{Display, Formatter, Error};
 
 /// The result used in the whole application.
 pub type AppResult<T> = Result<T, AppError>;
 
-/// The generic error used in the whole application.
-pub struct AppError
+/// The exit code if everything went fine.
+pub const EXIT_SUCCESS: i32 = 0;
+
+/// The exit code for errors without a more specific exit code.
+pub const EXIT_ERROR: i32 = 1;
+
+/// The exit code for invalid command line arguments.
+pub const EXIT_INVALID_USAGE: i32 = 2;
+
+/// The exit code if no conflicts have been found.
+pub const EXIT_NO_CONFLICTS: i32 = 3;
+
+/// The exit code if the user quit before all conflicts have been handled.
+pub const EXIT_QUIT: i32 = 4;
+
+/// The exit code if an operation wasn't permitted.
+pub const EXIT_PERMISSION_DENIED: i32 = 5;
+
+/// The exit code for all other I/O errors.
+pub const EXIT_IO_ERROR: i32 = 6;
+
+/// The exit code if a part of the directory hierarchy couldn't be scanned with `--strict`.
+pub const EXIT_INCOMPLETE_SCAN: i32 = 7;
+
+/// The exit code if the file operations of some conflicts failed and they have been skipped.
+pub const EXIT_FAILED_CONFLICTS: i32 = 8;
+
+/// The exit code if another run of confsolve holds the lock of the directory.
+pub const EXIT_LOCKED: i32 = 9;
+
+/// The errors used in the whole application.
+#[derive(Debug)]
+pub enum AppError
 {
-   error: String
+   /// invalid command line arguments or environment variables
+   InvalidUsage(String),
+
+   /// an I/O error, optionally with the path it concerns
+   Io {
+      error: io::Error,
+      path : Option<PathBuf>
+   },
+
+   /// parts of the directory hierarchy `dir` couldn't be scanned
+   IncompleteScan {
+      dir         : PathBuf,
+      num_warnings: usize
+   },
+
+   /// another run of confsolve, described by `holder`, holds the lock of `dir`
+   Locked {
+      dir   : PathBuf,
+      holder: String
+   },
+
+   /// all other errors
+   Other(String)
 }
 
 impl AppError
 {
    pub fn from_string(string: String) -> AppError
    {
-      AppError { error: string }
+      AppError::Other(string)
+   }
+
+   /// An I/O error `error` concerning `path`.
+   pub fn io(error: io::Error, path: &Path) -> AppError
+   {
+      AppError::Io { error, path: Some(path.to_path_buf()) }
+   }
+
+   /// The exit code of the application for the error.
+   pub fn exit_code(&self) -> i32
+   {
+      match *self {
+         AppError::InvalidUsage(..)   => EXIT_INVALID_USAGE,
+         AppError::IncompleteScan{..} => EXIT_INCOMPLETE_SCAN,
+         AppError::Locked{..}         => EXIT_LOCKED,
+         AppError::Other(..)          => EXIT_ERROR,
+         AppError::Io { ref error, .. } => {
+            match error.kind() {
+               io::ErrorKind::PermissionDenied => EXIT_PERMISSION_DENIED,
+               _                               => EXIT_IO_ERROR
+            }
+         }
+      }
    }
 }
 
@@ -23,7 +101,33 @@ impl Display for AppError
 {
    fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
    {
-      writeln!(f, "{}", self.error)
+      match *self {
+         AppError::InvalidUsage(ref msg) => write!(f, "Invalid usage: {}", msg),
+
+         AppError::Io { ref error, path: Some(ref path) } => write!(f, "'{}': {}", path.display(), error),
+         AppError::Io { ref error, path: None }           => write!(f, "{}", error),
+
+         AppError::IncompleteScan { ref dir, num_warnings } =>
+            write!(f, "Couldn't scan {} part(s) of '{}', aborting because of '--strict'!",
+                   num_warnings, dir.display()),
+
+         AppError::Locked { ref dir, ref holder } =>
+            write!(f, "The conflicts of '{}' are already being resolved by {}! Use '--force' to take over the lock.",
+                   dir.display(), holder),
+
+         AppError::Other(ref msg) => write!(f, "{}", msg)
+      }
+   }
+}
+
+impl error::Error for AppError
+{
+   fn source(&self) -> Option<&(dyn error::Error + 'static)>
+   {
+      match *self {
+         AppError::Io { ref error, .. } => Some(error),
+         _                              => None
+      }
    }
 }
 
@@ -31,6 +135,38 @@ impl From<io::Error> for AppError
 {
    fn from(err: io::Error) -> AppError
    {
-      AppError { error: format!("{}", err) }
+      AppError::Io { error: err, path: None }
    }
 }
+
+/// Adds the path an I/O error concerns to the error.
+pub trait WithPath<T>
+{
+   fn with_path(self, path: &Path) -> AppResult<T>;
+}
+
+impl<T> WithPath<T> for io::Result<T>
+{
+   fn with_path(self, path: &Path) -> AppResult<T>
+   {
+      self.map_err(|err| AppError::io(err, path))
+   }
+}
+
+#[test]
+fn tests()
+{
+   let denied = || io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+   let not_found = io::Error::new(io::ErrorKind::NotFound, "not found");
+
+   assert_eq!(AppError::from(denied()).exit_code(), EXIT_PERMISSION_DENIED);
+   assert_eq!(AppError::from(not_found).exit_code(), EXIT_IO_ERROR);
+   assert_eq!(AppError::InvalidUsage("blub".to_string()).exit_code(), EXIT_INVALID_USAGE);
+   assert_eq!(AppError::from_string("blub".to_string()).exit_code(), EXIT_ERROR);
+   assert_eq!(AppError::IncompleteScan { dir: PathBuf::from("dir"), num_warnings: 2 }.exit_code(), EXIT_INCOMPLETE_SCAN);
+
+   let result: io::Result<()> = Err(denied());
+   let err = result.with_path(Path::new("a/b")).unwrap_err();
+   assert_eq!(err.to_string(), "'a/b': denied");
+   assert!(error::Error::source(&err).is_some());
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::env;
+use std::path::{Path, PathBuf};
 use dirs;
 
 /// OS specific path to the application cache directory.
@@ -12,27 +13,87 @@ pub fn cache(app_name: &str) -> Option<PathBuf>
 }
 
 /// OS specific path for caches.
+///
+/// On unix this is `$XDG_CACHE_HOME` or - if not defined - `~/.cache`,
+/// on windows it's the local application data directory.
 pub fn cache_home() -> Option<PathBuf>
 {
    #[cfg(unix)]
    fn _cache_home() -> Option<PathBuf>
    {
-      dirs::home_dir().map(|mut dir| { dir.push(".cache"); dir })
+      xdg_home("XDG_CACHE_HOME", ".cache")
    }
 
    #[cfg(windows)]
    fn _cache_home() -> Option<PathBuf>
    {
-      dirs::home_dir().map(|mut dir| {
-         dir.push("Local Settings");
-         dir.push("Cache");
-         dir
-      })
+      dirs::cache_dir()
    }
 
    _cache_home()
 }
 
+/// OS specific path for user specific data files.
+///
+/// On unix this is `$XDG_DATA_HOME` or - if not defined - `~/.local/share`,
+/// on windows it's the roaming application data directory.
+pub fn data_home() -> Option<PathBuf>
+{
+   #[cfg(unix)]
+   fn _data_home() -> Option<PathBuf>
+   {
+      xdg_home("XDG_DATA_HOME", ".local/share")
+   }
+
+   #[cfg(windows)]
+   fn _data_home() -> Option<PathBuf>
+   {
+      dirs::data_dir()
+   }
+
+   _data_home()
+}
+
+/// OS specific path for user specific configuration files.
+///
+/// On unix this is `$XDG_CONFIG_HOME` or - if not defined - `~/.config`,
+/// on windows it's the roaming application data directory.
+pub fn config_home() -> Option<PathBuf>
+{
+   #[cfg(unix)]
+   fn _config_home() -> Option<PathBuf>
+   {
+      xdg_home("XDG_CONFIG_HOME", ".config")
+   }
+
+   #[cfg(windows)]
+   fn _config_home() -> Option<PathBuf>
+   {
+      dirs::config_dir()
+   }
+
+   _config_home()
+}
+
+/// Reads the XDG base directory from the environment variable `var`, falling
+/// back to `default` relative to the home directory if it isn't set.
+#[cfg(unix)]
+fn xdg_home(var: &str, default: &str) -> Option<PathBuf>
+{
+   xdg_dir(env::var_os(var).as_ref().map(Path::new), dirs::home_dir().as_deref(), default)
+}
+
+// The XDG base directory specification says that relative paths in
+// the variables are invalid and should be ignored, as well as empty ones.
+#[cfg(unix)]
+fn xdg_dir(env_dir: Option<&Path>, home_dir: Option<&Path>, default: &str) -> Option<PathBuf>
+{
+   match env_dir {
+      Some(dir) if dir.is_absolute() => Some(dir.to_path_buf()),
+      _ => home_dir.map(|dir| dir.join(default))
+   }
+}
+
 #[test]
 #[cfg(test)]
 fn tests()
@@ -42,15 +103,26 @@ fn tests()
    {
       match dirs::home_dir() {
          Some(mut dir) => {
-            dir.push(".cache");
+            match env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+               Some(ref xdg_dir) if xdg_dir.is_absolute() => dir = xdg_dir.clone(),
+               _ => dir.push(".cache")
+            }
+
             dir.push("blub");
             let dir_str = format!("{}", dir.display());
             let cache_str = format!("{}", cache("blub").unwrap().display());
             assert_eq!(dir_str, cache_str);
          }
 
-         None => assert!(false, "Couldn't get homedir!")
+         None => panic!("Couldn't get homedir!")
       }
+
+      let home = Path::new("/home/blub");
+      assert_eq!(xdg_dir(None, Some(home), ".cache"), Some(PathBuf::from("/home/blub/.cache")));
+      assert_eq!(xdg_dir(Some(Path::new("/xdg/cache")), Some(home), ".cache"), Some(PathBuf::from("/xdg/cache")));
+      assert_eq!(xdg_dir(Some(Path::new("rel/cache")), Some(home), ".cache"), Some(PathBuf::from("/home/blub/.cache")));
+      assert_eq!(xdg_dir(Some(Path::new("")), Some(home), ".cache"), Some(PathBuf::from("/home/blub/.cache")));
+      assert_eq!(xdg_dir(None, None, ".cache"), None);
    }
 
    _tests()
@@ -1,19 +1,90 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{self, Path, PathBuf};
 use std::fmt::{Display, Formatter, Error};
+use trash;
+use watch::{self, Policy};
+use file_conflict::{ConflictType, Wuala, Dropbox};
 
 pub use self::Command::{
    ResolveWuala,
    ResolveDropbox,
+   ResolveDefault,
+   ListTrash,
+   PurgeTrash,
+   PrintTrashStats,
+   RestoreTrash,
+   PrintLog,
+   Watch,
+   ListConflicts,
+   ShowConfig,
    PrintHelp,
    InvalidUsage
 };
 
+/// Options which modify how conflicts are searched for and resolved.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Options
+{
+   /// fail the run if a part of the directory hierarchy couldn't be scanned
+   pub strict: bool,
+
+   /// put the trashed files into this directory instead of the default one
+   pub trash_dir: Option<PathBuf>,
+
+   /// put the trashed files into the trash of the desktop instead of the private one
+   pub freedesktop_trash: bool,
+
+   /// store the contents of the trashed files only once in the private trash
+   pub dedup_trash: bool,
+
+   /// purge the trash sessions older than this number of seconds
+   pub older_than: Option<u64>,
+
+   /// purge the oldest trash sessions until the trash takes at most this number of bytes on disk
+   pub max_size: Option<u64>,
+
+   /// don't record the resolution of the conflicts in the session log
+   pub no_log: bool,
+
+   /// only show the events of the session log whose path contains this string
+   pub log_path: Option<String>,
+
+   /// only show the events of the session log whose date starts with this string
+   pub log_date: Option<String>,
+
+   /// write the summary of the run as Markdown or HTML into this file
+   pub report: Option<PathBuf>,
+
+   /// skip conflicts whose file operations failed instead of asking the user
+   pub keep_going: bool,
+
+   /// take over the lock of the directory held by another run of confsolve
+   pub force: bool,
+
+   /// what the watch mode does with new conflicts, by default `Policy::Notify`
+   pub policy: Option<Policy>,
+
+   /// scan the whole directory hierarchy instead of only the directories changed since the last scan
+   pub no_index: bool,
+
+   /// keep the original file of conflicts whose files differ only in their formatting
+   pub resolve_trivial: bool
+}
+
 #[derive(PartialEq, Debug)]
 pub enum Command
 {
-   ResolveWuala(PathBuf),
-   ResolveDropbox(PathBuf),
+   ResolveWuala(PathBuf, Options),
+   ResolveDropbox(PathBuf, Options),
+   ResolveDefault(PathBuf, Options),
+   ListTrash(Options),
+   PurgeTrash(Options),
+   PrintTrashStats(Options),
+   RestoreTrash(String, PathBuf, Options),
+   PrintLog(Options),
+   Watch(ConflictType, PathBuf, Options),
+   ListConflicts(ConflictType, PathBuf, Options),
+   ShowConfig(Option<PathBuf>),
    PrintHelp,
    InvalidUsage
 }
@@ -23,10 +94,23 @@ impl Display for Command
    fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
    {
       match *self {
-         ResolveWuala(ref path)   => write!(f, "ResolveWuala({})", path.display()),
-         ResolveDropbox(ref path) => write!(f, "ResolveDropbox({})", path.display()),
-         PrintHelp                => write!(f, "PrintHelp"),
-         InvalidUsage             => write!(f, "InvalidUsage")
+         ResolveWuala(ref path, _)   => write!(f, "ResolveWuala({})", path.display()),
+         ResolveDropbox(ref path, _) => write!(f, "ResolveDropbox({})", path.display()),
+         ResolveDefault(ref path, _) => write!(f, "ResolveDefault({})", path.display()),
+         ListTrash(_)                => write!(f, "ListTrash"),
+         PurgeTrash(_)               => write!(f, "PurgeTrash"),
+         PrintTrashStats(_)          => write!(f, "PrintTrashStats"),
+         RestoreTrash(ref session, ref dir, _)
+         => write!(f, "RestoreTrash({}, {})", session, dir.display()),
+         PrintLog(_)                 => write!(f, "PrintLog"),
+         Watch(conf_type, ref dir, _)
+         => write!(f, "Watch({}, {})", conf_type, dir.display()),
+         ListConflicts(conf_type, ref dir, _)
+         => write!(f, "ListConflicts({}, {})", conf_type, dir.display()),
+         ShowConfig(Some(ref dir))   => write!(f, "ShowConfig({})", dir.display()),
+         ShowConfig(None)            => write!(f, "ShowConfig"),
+         PrintHelp                   => write!(f, "PrintHelp"),
+         InvalidUsage                => write!(f, "InvalidUsage")
       }
    }
 }
@@ -39,46 +123,240 @@ pub fn get_command() -> Command
 pub fn print_help()
 {
    println!("
-Usage: confsolve wuala <dir>
-       confsolve dropbox <dir>
+Usage: confsolve wuala [options] <dir>
+       confsolve dropbox [options] <dir>
+       confsolve [options] <dir>
+       confsolve trash list [--trash-dir <dir>]
+       confsolve trash purge [--older-than <age>] [--max-size <size>] [--trash-dir <dir>]
+       confsolve trash stats [--trash-dir <dir>]
+       confsolve trash restore <session> <dir> [--trash-dir <dir>]
+       confsolve log [--path <path>] [--date <date>]
+       confsolve watch (wuala|dropbox) [--policy <policy>] [options] <dir>
+       confsolve list (wuala|dropbox) [--no-index] [--strict] <dir>
+       confsolve config show [<dir>]
        confsolve --help
 
 Options:
-  -h, --help   Show this message.\n");
+  -h, --help          Show this message.
+  --strict            Fail if a part of the directory hierarchy couldn't be scanned.
+  --trash-dir <dir>   Put trashed files into <dir> instead of the default trash directory.
+                      The default can also be set by the environment variable 'CONFSOLVE_TRASH_DIR'.
+  --freedesktop-trash Put trashed files into the trash of the desktop (freedesktop.org
+                      trash specification), where they can be restored by the file manager.
+  --dedup-trash       Store the contents of trashed files only once in the trash directory.
+  --no-log            Don't record the resolution of the conflicts in the session log.
+  --report <file>     Write the summary of the run into <file>, as HTML if <file>
+                      ends with '.html', otherwise as Markdown.
+  --keep-going        Skip conflicts whose file operations failed instead of asking
+                      whether to retry, skip or quit.
+  --force             Resolve the conflicts even if another run of confsolve holds the
+                      lock of the directory.
+  --resolve-trivial   Keep the original file of conflicts whose files differ only in line
+                      endings, trailing whitespace or a BOM, without asking.
+  --no-index          Scan the whole directory hierarchy instead of only the directories
+                      changed since the last scan.
+  --policy <policy>   What 'watch' does with new conflicts: 'notify' (default) reports them,
+                      'queue' queues them for the next interactive session, 'keep-original'
+                      moves the conflicting files to the trash and 'take-newest' takes the
+                      most recently modified file.
+  --path <path>       Only show the events of the session log whose path contains <path>.
+  --date <date>       Only show the events of the session log from <date>, e.g. '2015-06-01' or '2015-06'.
+  --older-than <age>  Purge the trash sessions older than <age>, e.g. '30d'.
+                      The units 's', 'm', 'h', 'd' and 'w' are supported.
+  --max-size <size>   Purge the oldest trash sessions until the trash is at most <size>, e.g. '2G'.
+                      The units 'K', 'M', 'G' and 'T' are supported.
+
+Configuration:
+  The defaults are read from 'confsolve/config.toml' inside of the configuration directory,
+  e.g. '~/.config/confsolve/config.toml', and from '.confsolve.toml' inside of <dir>.
+  'confsolve config show [<dir>]' prints the effective configuration.
+
+Environment:
+  CONFSOLVE_DIFF              The diff command, overriding the configured one, e.g.
+                              'meld --label {{left_label}} --label {{right_label}} {{left}} {{right}}'.
+  CONFSOLVE_TRASH_RETENTION   If set to an age, e.g. '30d', the trash sessions older than it
+                              are purged at the start of every run resolving conflicts.
+  CONFSOLVE_LOG_DIR           The directory of the session log, by default 'confsolve/log'
+                              inside of the data directory, e.g. '~/.local/share/confsolve/log'.
+
+Exit Codes:
+  0  success         1  error              2  invalid usage      3  no conflicts found
+  4  user quit       5  permission denied  6  other I/O error    7  incomplete scan (--strict)
+  8  some conflicts failed  9  directory locked by another run\n");
+}
+
+fn is_help_arg(arg: &str) -> bool
+{
+   arg == "--help" || arg == "-h"
 }
 
-fn is_help_arg(arg: &String) -> bool
+fn is_wuala_arg(arg: &str) -> bool
 {
-   *arg == "--help".to_string() || *arg == "-h".to_string()
+   arg == "wuala"
 }
 
-fn is_wuala_arg(arg: &String) -> bool
+fn is_dropbox_arg(arg: &str) -> bool
 {
-   *arg == "wuala".to_string()
+   arg == "dropbox"
 }
 
-fn is_dropbox_arg(arg: &String) -> bool
+fn is_trash_arg(arg: &str) -> bool
 {
-   *arg == "dropbox".to_string()
+   arg == "trash"
 }
 
-fn parse_args(args: &Vec<String>) -> Command
+fn is_watch_arg(arg: &str) -> bool
 {
-   match args.len() {
-      2 if is_help_arg(&args[1])
+   arg == "watch"
+}
+
+fn is_known_command(arg: &str) -> bool
+{
+   is_wuala_arg(arg) || is_dropbox_arg(arg) || is_trash_arg(arg) || is_watch_arg(arg)
+      || ["log", "list", "config"].contains(&arg)
+}
+
+// A single argument is only the directory of `confsolve [options] <dir>` if it looks
+// like one, so that a misspelled command - like `dropbx` - doesn't start resolving.
+fn is_dir_arg(arg: &str) -> bool
+{
+   ! is_known_command(arg) && (arg.contains('/') || arg.contains(path::MAIN_SEPARATOR) || Path::new(arg).is_dir())
+}
+
+fn parse_args(args: &[String]) -> Command
+{
+   let mut options = Options::default();
+   let mut args_left = Vec::new();
+   let mut args_iter = args.iter().skip(1);
+   while let Some(arg) = args_iter.next() {
+      match arg.as_str() {
+         "--strict" => options.strict = true,
+
+         "--freedesktop-trash" => options.freedesktop_trash = true,
+
+         "--dedup-trash" => options.dedup_trash = true,
+
+         "--trash-dir" => {
+            match args_iter.next() {
+               Some(dir) => options.trash_dir = Some(PathBuf::from(dir)),
+               None      => return InvalidUsage
+            }
+         }
+
+         "--no-log" => options.no_log = true,
+
+         "--keep-going" => options.keep_going = true,
+
+         "--force" => options.force = true,
+
+         "--no-index" => options.no_index = true,
+         "--resolve-trivial" => options.resolve_trivial = true,
+
+         "--policy" => {
+            match args_iter.next().and_then(|policy| watch::parse_policy(policy)) {
+               Some(policy) => options.policy = Some(policy),
+               None         => return InvalidUsage
+            }
+         }
+
+         "--report" => {
+            match args_iter.next() {
+               Some(file) => options.report = Some(PathBuf::from(file)),
+               None       => return InvalidUsage
+            }
+         }
+
+         "--path" => {
+            match args_iter.next() {
+               Some(path) => options.log_path = Some(path.to_string()),
+               None       => return InvalidUsage
+            }
+         }
+
+         "--date" => {
+            match args_iter.next() {
+               Some(date) => options.log_date = Some(date.to_string()),
+               None       => return InvalidUsage
+            }
+         }
+
+         "--older-than" => {
+            match args_iter.next().and_then(|age| trash::parse_age(age)) {
+               Some(secs) => options.older_than = Some(secs),
+               None       => return InvalidUsage
+            }
+         }
+
+         "--max-size" => {
+            match args_iter.next().and_then(|size| trash::parse_size(size)) {
+               Some(bytes) => options.max_size = Some(bytes),
+               None        => return InvalidUsage
+            }
+         }
+
+         _ => args_left.push(arg.as_str())
+      }
+   }
+
+   if options.freedesktop_trash && (options.trash_dir.is_some() || options.dedup_trash) {
+      return InvalidUsage;
+   }
+
+   match args_left.len() {
+      1 if is_help_arg(args_left[0])
       => PrintHelp,
 
-      3 if is_wuala_arg(&args[1]) && is_help_arg(&args[2])
+      1 if args_left[0] == "log"
+      => PrintLog(options),
+
+      2 if is_wuala_arg(args_left[0]) && is_help_arg(args_left[1])
       => PrintHelp,
 
-      3 if is_wuala_arg(&args[1])
-      => ResolveWuala(PathBuf::from(&args[2])),
+      2 if is_wuala_arg(args_left[0])
+      => ResolveWuala(PathBuf::from(args_left[1]), options),
 
-      3 if is_dropbox_arg(&args[1]) && is_help_arg(&args[2])
+      2 if is_dropbox_arg(args_left[0]) && is_help_arg(args_left[1])
       => PrintHelp,
 
-      3 if is_dropbox_arg(&args[1])
-      => ResolveDropbox(PathBuf::from(&args[2])),
+      2 if is_dropbox_arg(args_left[0])
+      => ResolveDropbox(PathBuf::from(args_left[1]), options),
+
+      2 if is_trash_arg(args_left[0]) && is_help_arg(args_left[1])
+      => PrintHelp,
+
+      2 if is_trash_arg(args_left[0]) && args_left[1] == "list"
+      => ListTrash(options),
+
+      2 if is_trash_arg(args_left[0]) && args_left[1] == "purge"
+           && (options.older_than.is_some() || options.max_size.is_some())
+      => PurgeTrash(options),
+
+      2 if is_trash_arg(args_left[0]) && args_left[1] == "stats"
+      => PrintTrashStats(options),
+
+      4 if is_trash_arg(args_left[0]) && args_left[1] == "restore"
+      => RestoreTrash(args_left[2].to_string(), PathBuf::from(args_left[3]), options),
+
+      3 if is_watch_arg(args_left[0]) && is_wuala_arg(args_left[1])
+      => Watch(Wuala, PathBuf::from(args_left[2]), options),
+
+      3 if is_watch_arg(args_left[0]) && is_dropbox_arg(args_left[1])
+      => Watch(Dropbox, PathBuf::from(args_left[2]), options),
+
+      3 if args_left[0] == "list" && is_wuala_arg(args_left[1])
+      => ListConflicts(Wuala, PathBuf::from(args_left[2]), options),
+
+      3 if args_left[0] == "list" && is_dropbox_arg(args_left[1])
+      => ListConflicts(Dropbox, PathBuf::from(args_left[2]), options),
+
+      2 if args_left[0] == "config" && args_left[1] == "show"
+      => ShowConfig(None),
+
+      3 if args_left[0] == "config" && args_left[1] == "show"
+      => ShowConfig(Some(PathBuf::from(args_left[2]))),
+
+      1 if is_dir_arg(args_left[0])
+      => ResolveDefault(PathBuf::from(args_left[0]), options),
 
       _ => InvalidUsage
    }
@@ -96,16 +374,82 @@ fn tests()
    let dir = "dir".to_string();
    let dir_path = PathBuf::from("dir");
 
-   assert_eq!(parse_args(&vec![confsolve.clone(), help.clone()]), PrintHelp);
-   assert_eq!(parse_args(&vec![confsolve.clone(), wuala.clone(), help.clone()]), PrintHelp);
-   assert_eq!(parse_args(&vec![confsolve.clone(), dropbox.clone(), help.clone()]), PrintHelp);
-   assert_eq!(parse_args(&vec![confsolve.clone(), wuala.clone(), h.clone()]), PrintHelp);
-   assert_eq!(parse_args(&vec![confsolve.clone(), dropbox.clone(), h.clone()]), PrintHelp);
-   assert_eq!(parse_args(&vec![confsolve.clone(), wuala.clone()]), InvalidUsage);
-   assert_eq!(parse_args(&vec![confsolve.clone(), dropbox.clone()]), InvalidUsage);
-   assert_eq!(parse_args(&vec![confsolve.clone(), argh.clone()]), InvalidUsage);
-   assert_eq!(parse_args(&vec![confsolve.clone()]), InvalidUsage);
-   assert_eq!(parse_args(&vec![confsolve.clone(), argh.clone(), argh.clone()]), InvalidUsage);
-   assert_eq!(parse_args(&vec![confsolve.clone(), wuala.clone(), dir.clone()]), ResolveWuala(dir_path.clone()));
-   assert_eq!(parse_args(&vec![confsolve.clone(), dropbox.clone(), dir.clone()]), ResolveDropbox(dir_path.clone()));
+   assert_eq!(parse_args(&[confsolve.clone(), help.clone()]), PrintHelp);
+   assert_eq!(parse_args(&[confsolve.clone(), wuala.clone(), help.clone()]), PrintHelp);
+   assert_eq!(parse_args(&[confsolve.clone(), dropbox.clone(), help.clone()]), PrintHelp);
+   assert_eq!(parse_args(&[confsolve.clone(), wuala.clone(), h.clone()]), PrintHelp);
+   assert_eq!(parse_args(&[confsolve.clone(), dropbox.clone(), h.clone()]), PrintHelp);
+   assert_eq!(parse_args(&[confsolve.clone(), wuala.clone()]), InvalidUsage);
+   assert_eq!(parse_args(&[confsolve.clone(), dropbox.clone()]), InvalidUsage);
+   assert_eq!(parse_args(&[confsolve.clone(), argh.clone()]), InvalidUsage);
+   assert_eq!(parse_args(::std::slice::from_ref(&confsolve)), InvalidUsage);
+   assert_eq!(parse_args(&[confsolve.clone(), argh.clone(), argh.clone()]), InvalidUsage);
+   assert_eq!(parse_args(&[confsolve.clone(), wuala.clone(), dir.clone()]), ResolveWuala(dir_path.clone(), Options::default()));
+   assert_eq!(parse_args(&[confsolve.clone(), dropbox.clone(), dir.clone()]), ResolveDropbox(dir_path.clone(), Options::default()));
+
+   let strict = Options { strict: true, ..Options::default() };
+   let trash = Options { trash_dir: Some(PathBuf::from("trash")), ..Options::default() };
+   let desktop = Options { freedesktop_trash: true, ..Options::default() };
+
+   assert_eq!(parse(&["./argh"]), ResolveDefault(PathBuf::from("./argh"), Options::default()));
+   assert_eq!(parse(&["."]), ResolveDefault(PathBuf::from("."), Options::default()));
+   assert_eq!(parse(&["config", "show"]), ShowConfig(None));
+   assert_eq!(parse(&["config", "show", "dir"]), ShowConfig(Some(dir_path.clone())));
+   assert_eq!(parse(&["config"]), InvalidUsage);
+   assert_eq!(parse(&["dropbox", "--strict", "dir"]), ResolveDropbox(dir_path.clone(), strict.clone()));
+   assert_eq!(parse(&["wuala", "dir", "--strict"]), ResolveWuala(dir_path.clone(), strict.clone()));
+   assert_eq!(parse(&["--strict"]), InvalidUsage);
+   assert_eq!(parse(&["wuala", "--trash-dir", "trash", "dir"]), ResolveWuala(dir_path.clone(), trash.clone()));
+   assert_eq!(parse(&["wuala", "dir", "--trash-dir"]), InvalidUsage);
+   assert_eq!(parse(&["dropbox", "--freedesktop-trash", "dir"]), ResolveDropbox(dir_path.clone(), desktop.clone()));
+   assert_eq!(parse(&["dropbox", "--freedesktop-trash", "--trash-dir", "trash", "dir"]), InvalidUsage);
+   assert_eq!(parse(&["trash", "list"]), ListTrash(Options::default()));
+   assert_eq!(parse(&["trash", "list", "--trash-dir", "trash"]), ListTrash(trash.clone()));
+   assert_eq!(parse(&["trash", "blub"]), InvalidUsage);
+   assert_eq!(parse(&["trash"]), InvalidUsage);
+   assert_eq!(parse(&["trash", "stats"]), PrintTrashStats(Options::default()));
+   assert_eq!(parse(&["trash", "purge"]), InvalidUsage);
+   assert_eq!(parse(&["trash", "restore", "2015-06-01_14-23-05", "dir"]),
+              RestoreTrash("2015-06-01_14-23-05".to_string(), dir_path.clone(), Options::default()));
+   assert_eq!(parse(&["trash", "restore", "dir"]), InvalidUsage);
+   assert_eq!(parse(&["wuala", "--dedup-trash", "dir"]),
+              ResolveWuala(dir_path.clone(), Options { dedup_trash: true, ..Options::default() }));
+   assert_eq!(parse(&["wuala", "--dedup-trash", "--freedesktop-trash", "dir"]), InvalidUsage);
+   assert_eq!(parse(&["log"]), PrintLog(Options::default()));
+   assert_eq!(parse(&["log", "--path", "notes", "--date", "2015-06"]),
+              PrintLog(Options { log_path: Some("notes".to_string()), log_date: Some("2015-06".to_string()),
+                                 ..Options::default() }));
+   assert_eq!(parse(&["log", "--path"]), InvalidUsage);
+   assert_eq!(parse(&["wuala", "--report", "report.md", "dir"]),
+              ResolveWuala(dir_path.clone(), Options { report: Some(PathBuf::from("report.md")), ..Options::default() }));
+   assert_eq!(parse(&["wuala", "--no-log", "dir"]),
+              ResolveWuala(dir_path.clone(), Options { no_log: true, ..Options::default() }));
+   assert_eq!(parse(&["dropbox", "dir", "--keep-going"]),
+              ResolveDropbox(dir_path.clone(), Options { keep_going: true, ..Options::default() }));
+   assert_eq!(parse(&["watch", "dropbox", "dir"]), Watch(Dropbox, dir_path.clone(), Options::default()));
+   assert_eq!(parse(&["watch", "wuala", "--policy", "take-newest", "dir"]),
+              Watch(Wuala, dir_path.clone(), Options { policy: Some(Policy::TakeNewest), ..Options::default() }));
+   assert_eq!(parse(&["watch", "wuala", "--policy", "blub", "dir"]), InvalidUsage);
+   assert_eq!(parse(&["watch", "dir"]), InvalidUsage);
+   assert_eq!(parse(&["dropbox", "--resolve-trivial", "dir"]),
+              ResolveDropbox(dir_path.clone(), Options { resolve_trivial: true, ..Options::default() }));
+   assert_eq!(parse(&["list", "wuala", "--no-index", "dir"]),
+              ListConflicts(Wuala, dir_path.clone(), Options { no_index: true, ..Options::default() }));
+   assert_eq!(parse(&["list", "dir"]), InvalidUsage);
+   assert_eq!(parse(&["wuala", "--force", "dir"]),
+              ResolveWuala(dir_path.clone(), Options { force: true, ..Options::default() }));
+   assert_eq!(parse(&["trash", "purge", "--older-than", "30x"]), InvalidUsage);
+   assert_eq!(parse(&["trash", "purge", "--older-than", "2d"]),
+              PurgeTrash(Options { older_than: Some(2 * 24 * 60 * 60), ..Options::default() }));
+   assert_eq!(parse(&["trash", "purge", "--max-size", "2K", "--older-than", "5s"]),
+              PurgeTrash(Options { older_than: Some(5), max_size: Some(2048), ..Options::default() }));
+}
+
+#[cfg(test)]
+fn parse(args: &[&str]) -> Command
+{
+   let mut all_args = vec!["confsolve".to_string()];
+   all_args.extend(args.iter().map(|a| a.to_string()));
+   println!("test: {:?}", all_args);
+   parse_args(&all_args)
 }
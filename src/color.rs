@@ -0,0 +1,102 @@
+use std::env;
+use std::fmt::{Display, Formatter, Error};
+
+/// When the output is colored.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorMode
+{
+   /// if stdout is a terminal and the environment variable `NO_COLOR` isn't set
+   Auto,
+   Always,
+   Never
+}
+
+impl Display for ColorMode
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         ColorMode::Auto   => write!(f, "auto"),
+         ColorMode::Always => write!(f, "always"),
+         ColorMode::Never  => write!(f, "never")
+      }
+   }
+}
+
+/// Parses a color mode in the form it's displayed, e.g. `auto`.
+pub fn parse_color_mode(input: &str) -> Option<ColorMode>
+{
+   [ColorMode::Auto, ColorMode::Always, ColorMode::Never].iter()
+      .find(|m| m.to_string() == input)
+      .cloned()
+}
+
+/// Colors text by ANSI escape sequences, if enabled.
+pub struct Colors
+{
+   enabled: bool
+}
+
+impl Colors
+{
+   pub fn new(mode: ColorMode) -> Colors
+   {
+      let enabled = match mode {
+         ColorMode::Always => true,
+         ColorMode::Never  => false,
+         ColorMode::Auto   => env::var_os("NO_COLOR").is_none() && stdout_is_terminal()
+      };
+
+      Colors { enabled }
+   }
+
+   /// Bold, for the conflicts.
+   pub fn conflict<T: Display>(&self, text: T) -> String
+   {
+      self.paint("1", text)
+   }
+
+   /// Yellow, for warnings.
+   pub fn warning<T: Display>(&self, text: T) -> String
+   {
+      self.paint("33", text)
+   }
+
+   /// Red, for errors.
+   pub fn error<T: Display>(&self, text: T) -> String
+   {
+      self.paint("31", text)
+   }
+
+   fn paint<T: Display>(&self, code: &str, text: T) -> String
+   {
+      if self.enabled {
+         format!("\x1b[{}m{}\x1b[0m", code, text)
+      } else {
+         text.to_string()
+      }
+   }
+}
+
+#[cfg(unix)]
+fn stdout_is_terminal() -> bool
+{
+   use libc;
+
+   unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+fn stdout_is_terminal() -> bool
+{
+   false
+}
+
+#[test]
+fn tests()
+{
+   assert_eq!(Colors::new(ColorMode::Always).error("blub"), "\x1b[31mblub\x1b[0m");
+   assert_eq!(Colors::new(ColorMode::Never).error("blub"), "blub");
+   assert_eq!(parse_color_mode("always"), Some(ColorMode::Always));
+   assert_eq!(parse_color_mode("blub"), None);
+}
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::fmt::{Display, Formatter, Error};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+use app_result::{AppResult, AppError, WithPath};
+use trash::format_size;
+use super::{text, is_binary, SNIFF_LEN};
+
+// Office documents (docx, xlsx, odt) and ebooks (epub) are zip archives, whose
+// contents are mostly XML entries. The archives are compared by their entries, and
+// the contents of the changed text entries are kept for the diff tool.
+//
+// Only the start of a changed entry is decompressed to tell if it's text, and only
+// text entries up to `MAX_TEXT_SIZE` are decompressed completely, so that large
+// media entries - or a zip bomb - don't end up in memory.
+
+// The maximal size of a text entry, whose contents are kept for the diff tool.
+const MAX_TEXT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How an entry differs between two archives.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Change
+{
+   Added,
+   Removed,
+   Changed
+}
+
+impl Display for Change
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Change::Added   => write!(f, "added"),
+         Change::Removed => write!(f, "removed"),
+         Change::Changed => write!(f, "changed")
+      }
+   }
+}
+
+/// An entry, which differs between the archives, with its sizes in both of them.
+pub struct EntryChange
+{
+   pub change    : Change,
+   pub name      : String,
+   pub left_size : Option<u64>,
+   pub right_size: Option<u64>
+}
+
+/// A changed entry, which is text in both archives and at most 16 MiB large, with its contents.
+pub struct TextChange
+{
+   pub name : String,
+   pub left : String,
+   pub right: String
+}
+
+/// The comparison of two zip archives by their entries.
+pub struct ArchiveComparison
+{
+   labels: [String; 2],
+
+   /// the differing entries, ordered by their names
+   pub changes: Vec<EntryChange>,
+
+   /// the number of entries, which are the same in both archives
+   pub unchanged: usize,
+
+   /// the contents of the changed text entries
+   pub text_changes: Vec<TextChange>
+}
+
+// The name, the size and the CRC-32 checksum of an entry.
+type EntryInfo = (String, u64, u32);
+
+impl ArchiveComparison
+{
+   /// Compares the archives `left` and `right`, which are given with their labels.
+   pub fn new(left: &(String, &Path), right: &(String, &Path)) -> AppResult<ArchiveComparison>
+   {
+      let (mut left_zip, mut right_zip) = (open(left.1)?, open(right.1)?);
+      let (left_entries, right_entries) = (entries(&mut left_zip, left.1)?, entries(&mut right_zip, right.1)?);
+
+      let mut comparison = ArchiveComparison {
+         labels      : [left.0.clone(), right.0.clone()],
+         changes     : Vec::new(),
+         unchanged   : 0,
+         text_changes: Vec::new()
+      };
+
+      for (name, size, crc32) in left_entries.iter() {
+         match right_entries.iter().find(|(n, _, _)| n == name) {
+            None => comparison.changes.push(EntryChange {
+               change: Change::Removed, name: name.clone(), left_size: Some(*size), right_size: None
+            }),
+
+            Some((_, other_size, other_crc32)) if other_size != size || other_crc32 != crc32 => {
+               comparison.changes.push(EntryChange {
+                  change: Change::Changed, name: name.clone(), left_size: Some(*size), right_size: Some(*other_size)
+               });
+
+               if let (Some(left_text), Some(right_text)) = (read_text(&mut left_zip, name, left.1)?,
+                                                             read_text(&mut right_zip, name, right.1)?) {
+                  comparison.text_changes.push(TextChange {
+                     name : name.clone(),
+                     left : readable(name, &left_text),
+                     right: readable(name, &right_text)
+                  });
+               }
+            }
+
+            Some(_) => comparison.unchanged += 1
+         }
+      }
+
+      for (name, size, _) in right_entries.iter() {
+         if ! left_entries.iter().any(|(n, _, _)| n == name) {
+            comparison.changes.push(EntryChange {
+               change: Change::Added, name: name.clone(), left_size: None, right_size: Some(*size)
+            });
+         }
+      }
+
+      comparison.changes.sort_by(|a, b| a.name.cmp(&b.name));
+      Ok(comparison)
+   }
+}
+
+// Formats the comparison as a list of the differing entries:
+//
+//    A  report.docx (current)
+//    B  report.docx (1) Version 2011-04-30 from dan
+//
+//    changed  word/document.xml      12.3 KiB -> 12.5 KiB
+//    added    word/media/image2.png  -> 3.0 KiB
+//
+impl Display for ArchiveComparison
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      writeln!(f, "Compared the archives:")?;
+      writeln!(f, "   A  {}", self.labels[0])?;
+      writeln!(f, "   B  {}", self.labels[1])?;
+
+      if self.changes.is_empty() {
+         return writeln!(f, "\nThe archives have the same {} entries.", self.unchanged);
+      }
+
+      writeln!(f)?;
+      let width = self.changes.iter().map(|c| c.name.chars().count()).max().unwrap_or(0);
+      for change in self.changes.iter() {
+         let size = |size: Option<u64>| size.map(format_size).unwrap_or_default();
+         let sizes = format!("{} -> {}", size(change.left_size), size(change.right_size));
+         writeln!(f, "   {:<7}  {:<width$}  {}", change.change.to_string(), change.name, sizes.trim(), width = width)?;
+      }
+
+      writeln!(f, "{} entries changed, {} unchanged.", self.changes.len(), self.unchanged)
+   }
+}
+
+fn open(file: &Path) -> AppResult<ZipArchive<File>>
+{
+   let zip = File::open(file).with_path(file)?;
+   ZipArchive::new(zip).map_err(|err| zip_error(file, err))
+}
+
+fn entries(zip: &mut ZipArchive<File>, file: &Path) -> AppResult<Vec<EntryInfo>>
+{
+   let mut entries = Vec::new();
+   for i in 0..zip.len() {
+      let entry = zip.by_index(i).map_err(|err| zip_error(file, err))?;
+      if ! entry.is_dir() {
+         entries.push((entry.name().to_string(), entry.size(), entry.crc32()));
+      }
+   }
+
+   Ok(entries)
+}
+
+// The contents of the entry `name` as text, none if it's binary or larger than `MAX_TEXT_SIZE`.
+fn read_text(zip: &mut ZipArchive<File>, name: &str, file: &Path) -> AppResult<Option<String>>
+{
+   if zip.by_name(name).map_err(|err| zip_error(file, err))?.size() > MAX_TEXT_SIZE
+      || is_binary(&read(zip, name, file, SNIFF_LEN)?) {
+      return Ok(None);
+   }
+
+   // the size in the archive might be wrong, so it's checked again after reading
+   let content = read(zip, name, file, MAX_TEXT_SIZE + 1)?;
+   if content.len() as u64 > MAX_TEXT_SIZE {
+      return Ok(None);
+   }
+
+   Ok(text(&content).map(|text| text.into_owned()))
+}
+
+// The first `len` bytes of the entry `name`.
+fn read(zip: &mut ZipArchive<File>, name: &str, file: &Path, len: u64) -> AppResult<Vec<u8>>
+{
+   let entry = zip.by_name(name).map_err(|err| zip_error(file, err))?;
+   let mut content = Vec::new();
+   entry.take(len).read_to_end(&mut content).map_err(|err| zip_error(file, ZipError::Io(err)))?;
+   Ok(content)
+}
+
+fn zip_error(file: &Path, err: ZipError) -> AppError
+{
+   AppError::from_string(format!("Couldn't read the archive '{}': {}!", file.display(), err))
+}
+
+// The XML of office documents is mostly written in one line, so for the diff
+// tool every tag of an XML entry is put into its own line.
+fn readable(name: &str, text: &str) -> String
+{
+   let lowercase = name.to_lowercase();
+   if lowercase.ends_with(".xml") || lowercase.ends_with(".rels") {
+      text.replace("><", ">\n<")
+   } else {
+      text.to_string()
+   }
+}
+
+#[test]
+fn tests()
+{
+   use std::fs;
+   use std::io::Write;
+   use zip::ZipWriter;
+   use zip::write::FileOptions;
+
+   let dir = ::std::env::temp_dir().join(format!("confsolve-archive-test-{}", ::std::process::id()));
+   fs::create_dir_all(&dir).unwrap();
+   let write_zip = |name: &str, entries: &[(&str, &[u8])]| {
+      let path = dir.join(name);
+      let mut zip = ZipWriter::new(File::create(&path).unwrap());
+      for &(name, content) in entries.iter() {
+         zip.start_file(name, FileOptions::default()).unwrap();
+         zip.write_all(content).unwrap();
+      }
+
+      zip.finish().unwrap();
+      path
+   };
+
+   let (large_left, large_right) = (vec![b'a'; MAX_TEXT_SIZE as usize + 1], vec![b'b'; MAX_TEXT_SIZE as usize + 1]);
+   let left = write_zip("a.docx", &[("mimetype", b"x"), ("word/document.xml", b"<w><p>Hello</p></w>"),
+                                    ("media/a.png", b"\x89PNG\0"), ("old.xml", b"<a/>"), ("large.txt", &large_left)]);
+   let right = write_zip("b.docx", &[("mimetype", b"x"), ("word/document.xml", b"<w><p>Hello!</p></w>"),
+                                     ("media/a.png", b"\x89PNG\0\0"), ("new.txt", b"new"), ("large.txt", &large_right)]);
+
+   let comparison = ArchiveComparison::new(&("a.docx".to_string(), &left), &("b.docx".to_string(), &right)).unwrap();
+   let changes = comparison.changes.iter().map(|c| (c.change, c.name.as_str())).collect::<Vec<(Change, &str)>>();
+   assert_eq!(changes, [(Change::Changed, "large.txt"), (Change::Changed, "media/a.png"), (Change::Added, "new.txt"),
+                        (Change::Removed, "old.xml"), (Change::Changed, "word/document.xml")]);
+   assert_eq!(comparison.unchanged, 1);
+   assert_eq!(comparison.text_changes.len(), 1);
+   assert_eq!(comparison.text_changes[0].right, "<w>\n<p>Hello!</p>\n</w>");
+   assert!(comparison.to_string().contains("\n   added    new.txt            -> 3 B\n"));
+   assert_eq!(comparison.text_changes[0].name, "word/document.xml");
+
+   let _ = fs::remove_dir_all(&dir);
+}
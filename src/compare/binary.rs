@@ -0,0 +1,319 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Error};
+use sha2::{Sha256, Digest};
+
+use app_result::{AppResult, WithPath};
+use trash::format_size;
+use super::{is_binary, read_start, SNIFF_LEN};
+
+// The number of bytes at the beginning of an image, which are read for its dimensions.
+// The start of frame segment of a JPEG image might come after large EXIF data.
+const IMAGE_HEADER_LEN: u64 = 256 * 1024;
+
+/// How the files of a conflict are compared, chosen by the extension of the
+/// original file - configurable by `[handlers]` - or by the sniffed content.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Handler
+{
+   /// shown by the diff tool
+   Text,
+
+   /// size, hash and type of the contents
+   Binary,
+
+   /// like `Binary` plus the dimensions of PNG, JPEG and GIF images
+   Image,
+
+   /// like `Binary` plus the number of pages
+   Pdf,
+
+   /// the row counts of the tables and the schemas of SQLite databases
+   Sqlite,
+
+   /// the entries of zip archives, like office documents, the changed text entries by the diff tool
+   Archive
+}
+
+impl Display for Handler
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Handler::Text   => write!(f, "text"),
+         Handler::Binary => write!(f, "binary"),
+         Handler::Image  => write!(f, "image"),
+         Handler::Pdf    => write!(f, "pdf"),
+         Handler::Sqlite  => write!(f, "sqlite"),
+         Handler::Archive => write!(f, "archive")
+      }
+   }
+}
+
+/// Parses a handler in the form it's displayed, e.g. `image`.
+pub fn parse_handler(input: &str) -> Option<Handler>
+{
+   [Handler::Text, Handler::Binary, Handler::Image, Handler::Pdf, Handler::Sqlite, Handler::Archive].iter()
+      .find(|h| h.to_string() == input)
+      .cloned()
+}
+
+/// The handler for `files`: the one configured for the extension of the first file
+/// in `by_extension`, otherwise the one fitting to the contents of the files.
+pub fn handler_for(files: &[&Path], by_extension: &BTreeMap<String, Handler>) -> AppResult<Handler>
+{
+   let extension = files[0].extension().map(|e| e.to_string_lossy().to_lowercase());
+   if let Some(handler) = extension.and_then(|e| by_extension.get(&e)) {
+      return Ok(*handler);
+   }
+
+   let mut handler = Handler::Text;
+   for file in files.iter() {
+      let sniffed = sniff_handler(&read_start(file, SNIFF_LEN)?);
+      if sniffed != Handler::Text {
+         // files of different types are compared as binary files
+         handler = if handler == Handler::Text || handler == sniffed { sniffed } else { Handler::Binary };
+      }
+   }
+
+   Ok(handler)
+}
+
+// The handler fitting to the start of a file. A known magic number decides the handler, also
+// if the start doesn't look binary - like the one of a PDF with uncompressed objects - and
+// only files without one are told apart by `is_binary`.
+fn sniff_handler(start: &[u8]) -> Handler
+{
+   match mime_type(start) {
+      "image/png" | "image/jpeg" | "image/gif" => Handler::Image,
+      "application/pdf"                        => Handler::Pdf,
+      "application/vnd.sqlite3"                => Handler::Sqlite,
+      "application/zip"                        => Handler::Archive,
+      "text/plain"                             => Handler::Text,
+      _                                        => Handler::Binary
+   }
+}
+
+/// The properties of a binary file shown by a handler.
+pub struct Properties
+{
+   pub label     : String,
+   pub properties: Vec<(&'static str, String)>
+}
+
+impl Handler
+{
+   /// Reads the properties of the file `file` with the description `label`.
+   pub fn properties(&self, label: &str, file: &Path) -> AppResult<Properties>
+   {
+      // the contents are hashed while they're read, only their start is kept
+      let mut hasher = Sha256::new();
+      let len = io::copy(&mut File::open(file).with_path(file)?, &mut hasher).with_path(file)?;
+      let hash = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+      let size = match len {
+         len if len < 1024 => format_size(len),
+         len               => format!("{} ({} bytes)", format_size(len), len)
+      };
+
+      let start = read_start(file, if *self == Handler::Image { IMAGE_HEADER_LEN } else { SNIFF_LEN })?;
+      let mut properties = vec![
+         ("Size", size),
+         ("SHA-256", hash),
+         ("Type", mime_type(&start).to_string())
+      ];
+
+      match *self {
+         Handler::Image => {
+            let dimensions = image_dimensions(&start)
+               .map(|(width, height)| format!("{}x{}", width, height))
+               .unwrap_or("unknown".to_string());
+
+            properties.push(("Dimensions", dimensions));
+         }
+
+         // the page objects might be anywhere in the document
+         Handler::Pdf => {
+            let content = fs::read(file).with_path(file)?;
+            let pages = pdf_pages(&content).map(|p| p.to_string()).unwrap_or("unknown".to_string());
+            properties.push(("Pages", pages));
+         }
+
+         Handler::Text | Handler::Binary | Handler::Sqlite | Handler::Archive => ()
+      }
+
+      Ok(Properties { label: label.to_string(), properties })
+   }
+}
+
+/// The comparison of binary files by their properties.
+pub struct BinaryComparison
+{
+   pub handler: Handler,
+   pub files  : Vec<Properties>
+}
+
+impl BinaryComparison
+{
+   /// Compares the files `files`, which are given with their labels, by `handler`.
+   pub fn new(handler: Handler, files: &[(String, &Path)]) -> AppResult<BinaryComparison>
+   {
+      let mut properties = Vec::new();
+      for (label, file) in files.iter() {
+         properties.push(handler.properties(label, file)?);
+      }
+
+      Ok(BinaryComparison { handler, files: properties })
+   }
+}
+
+// Formats the comparison as a list of the files with their properties,
+// followed by the names of the properties, which differ between the files.
+impl Display for BinaryComparison
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      writeln!(f, "Compared by the '{}' handler:", self.handler)?;
+      for file in self.files.iter() {
+         writeln!(f, "   {}", file.label)?;
+         for (name, value) in file.properties.iter() {
+            writeln!(f, "      {:<10} : {}", name, value)?;
+         }
+      }
+
+      let first = &self.files[0].properties;
+      let differing = first.iter()
+         .filter(|&&(name, ref value)| {
+            self.files.iter().any(|file| file.properties.iter().any(|&(n, ref v)| n == name && v != value))
+         })
+         .map(|&(name, _)| name)
+         .collect::<Vec<&str>>();
+
+      if differing.is_empty() {
+         writeln!(f, "The files are identical.")
+      } else {
+         writeln!(f, "The files differ in: {}.", differing.join(", "))
+      }
+   }
+}
+
+/// The MIME type of `content`, sniffed from its first bytes.
+pub fn mime_type(content: &[u8]) -> &'static str
+{
+   let magics: [(&[u8], &str); 9] = [
+      (b"\x89PNG\r\n\x1a\n", "image/png"),
+      (b"\xff\xd8\xff", "image/jpeg"),
+      (b"GIF87a", "image/gif"),
+      (b"GIF89a", "image/gif"),
+      (b"%PDF-", "application/pdf"),
+      (b"PK\x03\x04", "application/zip"),
+      (b"\x1f\x8b", "application/gzip"),
+      (b"SQLite format 3\0", "application/vnd.sqlite3"),
+      (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "application/x-ole-storage")
+   ];
+
+   magics.iter()
+      .find(|&&(magic, _)| content.starts_with(magic))
+      .map(|&(_, mime)| mime)
+      .unwrap_or(if is_binary(content) { "application/octet-stream" } else { "text/plain" })
+}
+
+// The width and height of a PNG, JPEG or GIF image.
+fn image_dimensions(content: &[u8]) -> Option<(u32, u32)>
+{
+   let be16 = |i: usize| -> Option<u32> { content.get(i..i + 2).map(|b| (b[0] as u32) << 8 | b[1] as u32) };
+   let le16 = |i: usize| -> Option<u32> { content.get(i..i + 2).map(|b| (b[1] as u32) << 8 | b[0] as u32) };
+   let be32 = |i: usize| -> Option<u32> { Some(be16(i)? << 16 | be16(i + 2)?) };
+
+   match mime_type(content) {
+      // the IHDR chunk is the first one
+      "image/png" => Some((be32(16)?, be32(20)?)),
+
+      "image/gif" => Some((le16(6)?, le16(8)?)),
+
+      // the dimensions are in the start of frame segment
+      "image/jpeg" => {
+         let mut i = 2;
+         loop {
+            if *content.get(i)? != 0xff {
+               return None;
+            }
+
+            let marker = *content.get(i + 1)?;
+            let is_sof = (0xc0..=0xcf).contains(&marker) && ! [0xc4, 0xc8, 0xcc].contains(&marker);
+            if is_sof {
+               return Some((be16(i + 7)?, be16(i + 5)?));
+            }
+
+            i += 2 + be16(i + 2)? as usize;
+         }
+      }
+
+      _ => None
+   }
+}
+
+// The number of pages of a PDF document, counted by its page objects. If they're inside
+// of compressed object streams, the largest page count of the page tree nodes is used.
+fn pdf_pages(content: &[u8]) -> Option<usize>
+{
+   let text = String::from_utf8_lossy(content).replace("/Type /", "/Type/");
+   let pages = text.match_indices("/Type/Page")
+      .filter(|&(i, m)| text[i + m.len()..].chars().next().map(|c| ! c.is_alphanumeric()).unwrap_or(true))
+      .count();
+
+   if pages > 0 {
+      return Some(pages);
+   }
+
+   text.match_indices("/Count ")
+      .filter_map(|(i, m)| {
+         let rest = &text[i + m.len()..];
+         rest[..rest.find(|c: char| ! c.is_ascii_digit()).unwrap_or(rest.len())].parse::<usize>().ok()
+      })
+      .max()
+}
+
+#[test]
+fn tests()
+{
+   let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+   png.extend_from_slice(&[0, 0, 2, 128, 0, 0, 1, 224]);
+   assert_eq!(mime_type(&png), "image/png");
+   assert_eq!(image_dimensions(&png), Some((640, 480)));
+
+   let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xc0, 0, 11, 8, 0, 100, 0, 200, 3];
+   assert_eq!(mime_type(&jpeg), "image/jpeg");
+   assert_eq!(image_dimensions(&jpeg), Some((200, 100)));
+
+   assert_eq!(image_dimensions(b"GIF89a\x0a\0\x14\0"), Some((10, 20)));
+
+   let pdf = b"%PDF-1.4\n1 0 obj << /Type /Pages /Count 2 >>\n2 0 obj << /Type /Page >>\n3 0 obj <</Type/Page>>\n";
+   assert_eq!(mime_type(pdf), "application/pdf");
+   assert_eq!(pdf_pages(pdf), Some(2));
+   assert_eq!(pdf_pages(b"%PDF-1.5\n<< /Count 7 >>"), Some(7));
+
+   assert_eq!(mime_type(b"hello"), "text/plain");
+   assert_eq!(mime_type(b"caf\xe9"), "text/plain");
+   assert_eq!(mime_type(b"\0\x01"), "application/octet-stream");
+
+   assert_eq!(sniff_handler(pdf), Handler::Pdf);
+   assert_eq!(sniff_handler(b"\x1f\x8b\x08"), Handler::Binary);
+   assert_eq!(sniff_handler(b"hello"), Handler::Text);
+   assert_eq!(sniff_handler(b"\0\x01"), Handler::Binary);
+
+   for handler in [Handler::Text, Handler::Binary, Handler::Image, Handler::Pdf, Handler::Sqlite, Handler::Archive] {
+      assert_eq!(parse_handler(&handler.to_string()), Some(handler));
+   }
+
+   let comparison = BinaryComparison {
+      handler: Handler::Image,
+      files  : vec![
+         Properties { label: "a.png".to_string(), properties: vec![("Size", "1 B".to_string()), ("Dimensions", "1x1".to_string())] },
+         Properties { label: "b.png".to_string(), properties: vec![("Size", "1 B".to_string()), ("Dimensions", "2x2".to_string())] }
+      ]
+   };
+
+   assert!(comparison.to_string().ends_with("\nThe files differ in: Dimensions.\n"));
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::Path;
+use std::fmt::{Display, Formatter, Error};
+
+use file_conflict::Conflict;
+use super::text;
+
+const BOM: &str = "\u{feff}";
+
+/// How a conflicting file differs from the original file.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difference
+{
+   Identical,
+
+   /// only the formatting differs, e.g. by editors of different platforms
+   Formatting { bom: bool, line_endings: bool, whitespace: bool },
+
+   /// the contents differ, or a file couldn't be read
+   Content
+}
+
+impl Difference
+{
+   /// If the difference doesn't change the contents.
+   pub fn is_trivial(&self) -> bool
+   {
+      *self != Difference::Content
+   }
+}
+
+impl Display for Difference
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Difference::Identical => write!(f, "is identical to the current file"),
+         Difference::Content   => write!(f, "differs in its contents"),
+         Difference::Formatting { bom, line_endings, whitespace } => {
+            let mut what = Vec::new();
+            if bom          { what.push("the BOM"); }
+            if line_endings { what.push("the line endings"); }
+            if whitespace   { what.push("whitespace"); }
+            write!(f, "differs only in {}", what.join(" and "))
+         }
+      }
+   }
+}
+
+/// Classifies how the contents of `file` differ from the ones of `orig_file`.
+pub fn classify(orig_file: &Path, file: &Path) -> Difference
+{
+   match (fs::read(orig_file), fs::read(file)) {
+      (Ok(orig), Ok(other)) => classify_contents(&orig, &other),
+      _                     => Difference::Content
+   }
+}
+
+/// Classifies the conflicting files of `conf`, in their order. Every file is read,
+/// so the result should be passed along instead of classifying the conflict again.
+pub fn classify_conflict(conf: &Conflict) -> Vec<Difference>
+{
+   conf.conflicting_files.iter()
+      .map(|conf_file| classify(&conf.original_path, &conf_file.path))
+      .collect()
+}
+
+fn classify_contents(orig: &[u8], other: &[u8]) -> Difference
+{
+   if orig == other {
+      return Difference::Identical;
+   }
+
+   let (orig, other) = match (text(orig), text(other)) {
+      (Some(orig), Some(other)) => (orig, other),
+      _                         => return Difference::Content
+   };
+
+   let (orig, other) = (orig.as_ref(), other.as_ref());
+
+   // a file might have several BOMs, e.g. by adding one to a file which already had one
+   let num_boms = |text: &str| (text.len() - text.trim_start_matches(BOM).len()) / BOM.len();
+   let bom = num_boms(orig) != num_boms(other);
+   let (orig, other) = (orig.trim_start_matches(BOM), other.trim_start_matches(BOM));
+   if orig == other {
+      // the same text in different encodings, like UTF-8 and Latin-1
+      return if bom { Difference::Formatting { bom, line_endings: false, whitespace: false } } else { Difference::Content };
+   }
+
+   // line endings of Windows (CRLF), of Unix (LF) and of old Macs (CR)
+   let lf = |text: &str| text.replace("\r\n", "\n").replace('\r', "\n");
+   let (orig_lf, other_lf) = (lf(orig), lf(other));
+   if orig_lf == other_lf {
+      return Difference::Formatting { bom, line_endings: true, whitespace: false };
+   }
+
+   if normalize_whitespace(&orig_lf) == normalize_whitespace(&other_lf) {
+      let line_endings = line_ending_kinds(orig) != line_ending_kinds(other);
+      return Difference::Formatting { bom, line_endings, whitespace: true };
+   }
+
+   Difference::Content
+}
+
+// If `text` has line endings of Windows (CRLF), of old Macs (CR) and of Unix (LF).
+fn line_ending_kinds(text: &str) -> (bool, bool, bool)
+{
+   let crlf = text.matches("\r\n").count();
+   (crlf > 0, text.matches('\r').count() > crlf, text.matches('\n').count() > crlf)
+}
+
+// Removes the whitespace at the end of the lines and the empty lines at the end.
+// Indentation and whitespace inside of the lines are kept, because they might
+// be significant, e.g. in YAML or Python.
+fn normalize_whitespace(text: &str) -> String
+{
+   let lines = text.lines()
+      .map(|line| line.trim_end())
+      .collect::<Vec<&str>>();
+
+   lines.join("\n").trim_end().to_string()
+}
+
+#[test]
+fn tests()
+{
+   let formatting = |bom, line_endings, whitespace| Difference::Formatting { bom, line_endings, whitespace };
+   assert_eq!(classify_contents(b"a\nb\n", b"a\nb\n"), Difference::Identical);
+   assert_eq!(classify_contents(b"a\nb\n", b"a\r\nb\r\n"), formatting(false, true, false));
+   assert_eq!(classify_contents("\u{feff}a\nb\n".as_bytes(), b"a\r\nb\r\n"), formatting(true, true, false));
+   assert_eq!(classify_contents(b"a b\n\tc\n", b"a b \n\tc\t\n\n"), formatting(false, false, true));
+   assert_eq!(classify_contents(b"a:\n  b: 1\n", b"a:\n    b: 1\n"), Difference::Content);
+   assert_eq!(classify_contents(b"a  b\n", b"a b\n"), Difference::Content);
+   assert_eq!(classify_contents(b"a\nb\n", b"a\nc\n"), Difference::Content);
+   assert_eq!(classify_contents(b"a\0", b"a\0\0"), Difference::Content);
+   assert_eq!(classify_contents(b"caf\xe9\n", b"caf\xe9\r\n"), formatting(false, true, false));
+   assert_eq!(classify_contents(b"caf\xe9\n", b"caf\xfc\n"), Difference::Content);
+   assert_eq!(classify_contents("\u{feff}\u{feff}a\n".as_bytes(), "\u{feff}a\n".as_bytes()), formatting(true, false, false));
+   assert_eq!(classify_contents(b"caf\xe9\n", "caf\u{e9}\n".as_bytes()), Difference::Content);
+   assert_eq!(classify_contents(b"a\rb\r", b"a\nb\n"), formatting(false, true, false));
+   assert_eq!(classify_contents(b"a\rb\r", b"a\r\nb \r\n"), formatting(false, true, true));
+   assert_eq!(formatting(true, true, false).to_string(), "differs only in the BOM and the line endings");
+   assert!(! Difference::Content.is_trivial());
+}
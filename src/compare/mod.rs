@@ -0,0 +1,199 @@
+use std::fs::{self, File};
+use std::str;
+use std::io::Read;
+use std::borrow::Cow;
+use std::path::Path;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Error};
+use sha2::{Sha256, Digest};
+
+use app_result::{AppResult, WithPath};
+use trash::format_size;
+
+pub use self::binary::{
+   Handler,
+   BinaryComparison,
+   parse_handler,
+   handler_for
+};
+
+pub use self::formatting::{Difference, classify_conflict};
+
+pub use self::sqlite::{SqliteComparison, dump_sqlite};
+
+pub use self::archive::ArchiveComparison;
+
+mod binary;
+mod formatting;
+mod sqlite;
+mod archive;
+
+/// A comparison of the original file with all of its conflicting files,
+/// which shows which of them have the same contents.
+pub struct Comparison
+{
+   versions: Vec<Version>
+}
+
+// One compared file.
+struct Version
+{
+   label  : String,
+   size   : u64,
+
+   // the number of lines, none for a binary file
+   lines  : Option<usize>,
+
+   // files with the same contents have the same group, numbered by their first occurrence
+   group  : usize,
+
+   // the number of lines added and removed compared to the first file,
+   // none if one of both is binary
+   changes: Option<(usize, usize)>
+}
+
+impl Comparison
+{
+   /// Compares the contents of `files`, which are given with their labels.
+   /// The first file is the one the others are compared to.
+   pub fn new(files: &[(String, &Path)]) -> AppResult<Comparison>
+   {
+      let mut contents = Vec::new();
+      for &(_, file) in files.iter() {
+         contents.push(fs::read(file).with_path(file)?);
+      }
+
+      let mut groups = HashMap::new();
+      let mut versions = Vec::new();
+      for ((label, _), content) in files.iter().zip(contents.iter()) {
+         let num_groups = groups.len();
+         let group = *groups.entry(Sha256::digest(content)).or_insert(num_groups);
+         versions.push(Version {
+            label  : label.clone(),
+            size   : content.len() as u64,
+            lines  : text(content).map(|t| t.lines().count()),
+            group,
+            changes: match (text(&contents[0]), text(content)) {
+               (Some(first), Some(text)) => Some(line_changes(&first, &text)),
+               _                         => None
+            }
+         });
+      }
+
+      Ok(Comparison { versions })
+   }
+
+   /// The number of different contents of the compared files.
+   pub fn num_groups(&self) -> usize
+   {
+      self.versions.iter().map(|v| v.group + 1).max().unwrap_or(0)
+   }
+}
+
+// Formats the comparison as a table, with the same letter for files with the same contents:
+//
+//    A  notes.txt (current)          120 B   8 lines
+//    B  (1) Version 2011-04-30 from  135 B   9 lines   +1 -0 lines
+//    A  (2) Version 2011-05-02 from  120 B   8 lines   identical to the current file
+//
+impl Display for Comparison
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      writeln!(f, "Compared {} files, {} different version(s):", self.versions.len(), self.num_groups())?;
+      let label_width = self.versions.iter().map(|v| v.label.chars().count()).max().unwrap_or(0);
+      for (i, version) in self.versions.iter().enumerate() {
+         let lines = match version.lines {
+            Some(lines) => format!("{} lines", lines),
+            None        => "binary".to_string()
+         };
+
+         let compared = match version.changes {
+            _ if i == 0             => String::new(),
+            _ if version.group == 0 => "identical to the current file".to_string(),
+            Some((added, removed))  => format!("+{} -{} lines", added, removed),
+            None                    => "differs from the current file".to_string()
+         };
+
+         let group = (b'A' + (version.group % 26) as u8) as char;
+         let line = format!("   {}  {:<width$}  {:>10}  {:>10}   {}", group, version.label, format_size(version.size), lines,
+                            compared, width = label_width);
+
+         writeln!(f, "{}", line.trim_end())?;
+      }
+
+      Ok(())
+   }
+}
+
+// The number of bytes at the beginning of a file, by which it's
+// told whether the file is binary and which type it has.
+const SNIFF_LEN: u64 = 8000;
+
+// If the contents are binary, like by git recognized by a null byte at the beginning.
+fn is_binary(content: &[u8]) -> bool
+{
+   content.iter().take(SNIFF_LEN as usize).any(|b| *b == 0)
+}
+
+// The contents as text, none if they're binary. Contents which aren't UTF-8 - like
+// Latin-1 or Windows-1252 ones - are read as Latin-1, which maps every byte to its
+// own character, so that different contents never become the same text.
+fn text(content: &[u8]) -> Option<Cow<'_, str>>
+{
+   if is_binary(content) {
+      return None;
+   }
+
+   match str::from_utf8(content) {
+      Ok(text) => Some(Cow::Borrowed(text)),
+      Err(_)   => Some(Cow::Owned(content.iter().map(|b| *b as char).collect()))
+   }
+}
+
+// The first `len` bytes of `file`, for sniffing its type without reading all of it.
+fn read_start(file: &Path, len: u64) -> AppResult<Vec<u8>>
+{
+   let mut start = Vec::new();
+   File::open(file).with_path(file)?.take(len).read_to_end(&mut start).with_path(file)?;
+   Ok(start)
+}
+
+// Counts the lines of `text`, which aren't in `first`, and the lines of `first`,
+// which aren't in `text`. Moved lines aren't counted.
+fn line_changes(first: &str, text: &str) -> (usize, usize)
+{
+   let mut counts = HashMap::new();
+   for line in first.lines() {
+      *counts.entry(line).or_insert(0isize) += 1;
+   }
+
+   for line in text.lines() {
+      *counts.entry(line).or_insert(0isize) -= 1;
+   }
+
+   let added = counts.values().filter(|c| **c < 0).map(|c| (-*c) as usize).sum();
+   let removed = counts.values().filter(|c| **c > 0).map(|c| *c as usize).sum();
+   (added, removed)
+}
+
+#[test]
+fn tests()
+{
+   assert_eq!(line_changes("a\nb\nc\n", "a\nc\nd\ne\n"), (2, 1));
+   assert_eq!(line_changes("a\nb\n", "b\na\n"), (0, 0));
+   assert_eq!(text(b"a\0b"), None);
+   assert_eq!(text(b"ab").as_deref(), Some("ab"));
+   assert_eq!(text(b"caf\xe9\n").as_deref(), Some("caf\u{e9}\n"));
+
+   let versions = vec![
+      Version { label: "x.txt".to_string(), size: 4, lines: Some(2), group: 0, changes: Some((0, 0)) },
+      Version { label: "(1) blub".to_string(), size: 6, lines: Some(3), group: 1, changes: Some((1, 0)) },
+      Version { label: "(2) blah".to_string(), size: 4, lines: Some(2), group: 0, changes: Some((0, 0)) }
+   ];
+
+   let comparison = Comparison { versions }.to_string();
+   assert!(comparison.starts_with("Compared 3 files, 2 different version(s):\n"));
+   assert!(comparison.contains("   B  (1) blub         6 B     3 lines   +1 -0 lines\n"));
+   assert!(comparison.contains("   A  (2) blah         4 B     2 lines   identical to the current file\n"));
+}
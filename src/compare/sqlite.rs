@@ -0,0 +1,271 @@
+use std::fs;
+use std::path::Path;
+use std::fmt::{Display, Formatter, Error};
+use rusqlite::{Connection, OpenFlags};
+use rusqlite::types::ValueRef;
+
+use app_result::{AppResult, AppError, WithPath};
+
+/// An object of the schema of a database, like a table or an index.
+struct Object
+{
+   /// `table`, `index`, `view` or `trigger`
+   kind: String,
+   name: String,
+
+   /// the SQL creating the object, with collapsed whitespace
+   sql : String,
+
+   /// the number of rows of a table
+   rows: Option<i64>
+}
+
+/// The schema and the row counts of a SQLite database.
+struct Database
+{
+   label  : String,
+   objects: Vec<Object>
+}
+
+impl Database
+{
+   fn read(label: &str, file: &Path) -> AppResult<Database>
+   {
+      let conn = open(file)?;
+      let mut objects = query_schema(&conn).map_err(|err| sqlite_error(file, err))?;
+      for object in objects.iter_mut().filter(|o| o.kind == "table") {
+         let count = format!("SELECT count(*) FROM {}", quote_name(&object.name));
+         object.rows = Some(conn.query_row(&count, [], |row| row.get(0)).map_err(|err| sqlite_error(file, err))?);
+      }
+
+      Ok(Database { label: label.to_string(), objects })
+   }
+
+   fn get(&self, kind: &str, name: &str) -> Option<&Object>
+   {
+      self.objects.iter().find(|o| o.kind == kind && o.name == name)
+   }
+}
+
+/// The comparison of SQLite databases by the row counts of their tables and by their schemas.
+pub struct SqliteComparison
+{
+   databases: Vec<Database>
+}
+
+impl SqliteComparison
+{
+   /// Compares the databases `files`, which are given with their labels.
+   /// The first database is the one the others are compared to.
+   pub fn new(files: &[(String, &Path)]) -> AppResult<SqliteComparison>
+   {
+      let mut databases = Vec::new();
+      for (label, file) in files.iter() {
+         databases.push(Database::read(label, file)?);
+      }
+
+      Ok(SqliteComparison { databases })
+   }
+}
+
+// Formats the comparison as a table of the row counts, followed by the schema differences:
+//
+//    A  notes.db (current)
+//    B  notes.db (1) Version 2011-04-30 from dan
+//
+//    Table        A      B
+//    notes       12     14   +2
+//    tags         3      -
+//
+//    Schema differences:
+//       table 'tags' is missing in B
+//
+impl Display for SqliteComparison
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      writeln!(f, "Compared the SQLite databases:")?;
+      for (i, db) in self.databases.iter().enumerate() {
+         writeln!(f, "   {}  {}", letter(i), db.label)?;
+      }
+
+      let mut tables = Vec::new();
+      let mut objects = Vec::new();
+      for object in self.databases.iter().flat_map(|db| db.objects.iter()) {
+         if ! objects.contains(&(&object.kind, &object.name)) {
+            objects.push((&object.kind, &object.name));
+            if object.kind == "table" {
+               tables.push(&object.name);
+            }
+         }
+      }
+
+      let width = tables.iter().map(|t| t.chars().count()).max().unwrap_or(0).max(5);
+      let mut header = format!("\n   {:<width$}", "Table", width = width);
+      for i in 0..self.databases.len() {
+         header.push_str(&format!("  {:>8}", letter(i)));
+      }
+
+      writeln!(f, "{}", header)?;
+      for table in tables.iter() {
+         let mut line = format!("   {:<width$}", table, width = width);
+         for db in self.databases.iter() {
+            let rows = db.get("table", table).and_then(|o| o.rows).map(|r| r.to_string()).unwrap_or("-".to_string());
+            line.push_str(&format!("  {:>8}", rows));
+         }
+
+         writeln!(f, "{}", line)?;
+      }
+
+      let first = &self.databases[0];
+      let mut differences = Vec::new();
+      for &(kind, name) in objects.iter() {
+         for (i, db) in self.databases.iter().enumerate().skip(1) {
+            match (first.get(kind, name), db.get(kind, name)) {
+               (Some(_), None) => differences.push(format!("{} '{}' is missing in {}", kind, name, letter(i))),
+               (None, Some(o)) => differences.push(format!("{} '{}' is only in {}: {}", kind, name, letter(i), o.sql)),
+               (Some(a), Some(b)) if a.sql != b.sql => {
+                  differences.push(format!("{} '{}' differs in {}: {}", kind, name, letter(i), b.sql));
+               }
+
+               _ => ()
+            }
+         }
+      }
+
+      if differences.is_empty() {
+         writeln!(f, "\nThe schemas are identical.")
+      } else {
+         writeln!(f, "\nSchema differences:")?;
+         for difference in differences.iter() {
+            writeln!(f, "   {}", difference)?;
+         }
+
+         Ok(())
+      }
+   }
+}
+
+/// Writes the SQL dump of the database `file` - its schema and the rows of its
+/// tables as `INSERT` statements - into `dump_file`, e.g. for the diff tool.
+pub fn dump_sqlite(file: &Path, dump_file: &Path) -> AppResult<()>
+{
+   let conn = open(file)?;
+   let dump = dump(&conn).map_err(|err| sqlite_error(file, err))?;
+   fs::write(dump_file, dump).with_path(dump_file)
+}
+
+fn dump(conn: &Connection) -> rusqlite::Result<String>
+{
+   let objects = query_schema(conn)?;
+   let mut sql = String::new();
+   for object in objects.iter() {
+      sql.push_str(&format!("{};\n", object.sql));
+      if object.kind != "table" {
+         continue;
+      }
+
+      let mut stmt = conn.prepare(&format!("SELECT * FROM {}", quote_name(&object.name)))?;
+      let num_columns = stmt.column_count();
+      let mut rows = stmt.query([])?;
+      while let Some(row) = rows.next()? {
+         let mut values = Vec::new();
+         for i in 0..num_columns {
+            values.push(sql_value(row.get_ref(i)?));
+         }
+
+         sql.push_str(&format!("INSERT INTO {} VALUES({});\n", quote_name(&object.name), values.join(",")));
+      }
+   }
+
+   Ok(sql)
+}
+
+// The tables first - so that the dump can be read by sqlite - then the indexes,
+// views and triggers, each ordered by their names.
+fn query_schema(conn: &Connection) -> rusqlite::Result<Vec<Object>>
+{
+   let mut stmt = conn.prepare("SELECT type, name, sql FROM sqlite_master \
+                                WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' \
+                                ORDER BY type != 'table', type, name")?;
+
+   let objects = stmt.query_map([], |row| {
+      let sql: String = row.get(2)?;
+      Ok(Object {
+         kind: row.get(0)?,
+         name: row.get(1)?,
+         sql : sql.split_whitespace().collect::<Vec<&str>>().join(" "),
+         rows: None
+      })
+   })?;
+
+   objects.collect()
+}
+
+// The database is only read, so it isn't changed while the conflict is resolved.
+fn open(file: &Path) -> AppResult<Connection>
+{
+   Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+      .map_err(|err| sqlite_error(file, err))
+}
+
+fn sqlite_error(file: &Path, err: rusqlite::Error) -> AppError
+{
+   AppError::from_string(format!("Couldn't read the SQLite database '{}': {}!", file.display(), err))
+}
+
+fn quote_name(name: &str) -> String
+{
+   format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_value(value: ValueRef) -> String
+{
+   match value {
+      ValueRef::Null       => "NULL".to_string(),
+      ValueRef::Integer(i) => i.to_string(),
+      ValueRef::Real(r)    => format!("{:?}", r),
+      ValueRef::Text(t)    => format!("'{}'", String::from_utf8_lossy(t).replace('\'', "''")),
+      ValueRef::Blob(b)    => format!("X'{}'", b.iter().map(|b| format!("{:02X}", b)).collect::<String>())
+   }
+}
+
+fn letter(i: usize) -> char
+{
+   (b'A' + (i % 26) as u8) as char
+}
+
+#[test]
+fn tests()
+{
+   let dir = ::std::env::temp_dir().join(format!("confsolve-sqlite-test-{}", ::std::process::id()));
+   fs::create_dir_all(&dir).unwrap();
+   let (db1, db2, dump_file) = (dir.join("a.db"), dir.join("b.db"), dir.join("a.sql"));
+   let _ = fs::remove_file(&db1);
+   let _ = fs::remove_file(&db2);
+
+   Connection::open(&db1).unwrap().execute_batch(
+      "CREATE TABLE notes (id INTEGER PRIMARY KEY, text TEXT, data BLOB);
+       INSERT INTO notes VALUES (1, 'it''s', X'00FF'), (2, NULL, NULL);
+       CREATE TABLE tags (name TEXT);").unwrap();
+
+   Connection::open(&db2).unwrap().execute_batch(
+      "CREATE TABLE notes (id INTEGER PRIMARY KEY, text TEXT, data BLOB, done INTEGER);
+       INSERT INTO notes VALUES (1, 'a', NULL, 0);
+       CREATE INDEX notes_done ON notes (done);").unwrap();
+
+   let comparison = SqliteComparison::new(&[("a.db".to_string(), &db1), ("b.db".to_string(), &db2)]).unwrap().to_string();
+   assert!(comparison.contains("\n   Table         A         B\n   notes         2         1\n   tags          0         -\n"));
+   assert!(comparison.contains("   table 'tags' is missing in B\n"));
+   assert!(comparison.contains("   table 'notes' differs in B: CREATE TABLE notes (id INTEGER PRIMARY KEY, text TEXT, data BLOB, done INTEGER)\n"));
+   assert!(comparison.contains("   index 'notes_done' is only in B: CREATE INDEX notes_done ON notes (done)\n"));
+
+   dump_sqlite(&db1, &dump_file).unwrap();
+   assert_eq!(fs::read_to_string(&dump_file).unwrap(),
+              "CREATE TABLE notes (id INTEGER PRIMARY KEY, text TEXT, data BLOB);\n\
+               INSERT INTO \"notes\" VALUES(1,'it''s',X'00FF');\n\
+               INSERT INTO \"notes\" VALUES(2,NULL,NULL);\n\
+               CREATE TABLE tags (name TEXT);\n");
+
+   let _ = fs::remove_dir_all(&dir);
+}
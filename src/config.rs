@@ -0,0 +1,463 @@
+use std::env;
+use std::io::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Error};
+use glob::Pattern;
+use toml::Value;
+use toml::value::Table;
+
+use app_result::{AppResult, AppError, WithPath};
+use file_conflict::{ConflictType, Wuala, Dropbox};
+use user_reply::Keys;
+use color::{self, ColorMode};
+use watch::{self, Policy};
+use args::Options;
+use trash;
+use tool;
+use compare::{self, Handler};
+use appdirs;
+
+/// The name of the configuration file inside of a start directory, overriding
+/// the configuration of the user for the conflicts of this directory.
+pub const FOLDER_CONFIG: &str = ".confsolve.toml";
+
+/// The effective configuration of confsolve.
+///
+/// The configuration is read - in this order, later ones overriding earlier ones - from
+/// the file `confsolve/config.toml` inside of the configuration directory of the user,
+/// the file `.confsolve.toml` inside of the start directory and the environment
+/// variables. The command line options override the configuration.
+///
+/// The configuration file of a start directory might come from another member of a
+/// shared folder, therefore it can only set what changes how conflicts are shown:
+/// it can't set the commands `diff`, `diff_all` and `merge`, which are executed,
+/// the `trash` settings, `policy`, `resolve_trivial` and `keep_going`, by which
+/// files would be removed without asking, and `exclude`, which hides conflicts.
+#[derive(Clone, Debug)]
+pub struct Config
+{
+   /// the conflict type resolved if none is given on the command line
+   pub provider: Option<ConflictType>,
+
+   /// the command line showing the difference of two files, see `tool`
+   pub diff: String,
+
+   /// the command line comparing all files of a conflict at once, see `tool`
+   pub diff_all: Option<String>,
+
+   /// the command line merging two files, see `tool`
+   pub merge: Option<String>,
+
+   /// files and directories matching one of these patterns aren't resolved
+   pub exclude: Vec<Pattern>,
+
+   /// when the output is colored
+   pub color: ColorMode,
+
+   /// skip conflicts whose file operations failed instead of asking the user
+   pub keep_going: bool,
+
+   /// keep the original file of conflicts whose files differ only in their formatting
+   pub resolve_trivial: bool,
+
+   /// what the watch mode does with new conflicts
+   pub policy: Policy,
+
+   /// show the difference of SQLite databases also by the diff tool with their SQL dumps
+   pub sqlite_dump: bool,
+
+   /// the private trash directory, the default one if none
+   pub trash_dir: Option<PathBuf>,
+
+   /// put the trashed files into the trash of the desktop
+   pub freedesktop_trash: bool,
+
+   /// store the contents of the trashed files only once in the private trash
+   pub dedup_trash: bool,
+
+   /// the age of the trash sessions purged at the start of a run, e.g. `30d`
+   pub retention: Option<String>,
+
+   /// the keys of the replies
+   pub keys: Keys,
+
+   /// the comparison handlers by the lowercase file extension, e.g. `svg` => `Handler::Text`
+   pub handlers: BTreeMap<String, Handler>,
+
+   /// the configuration files read, in the order they've been read
+   pub files: Vec<PathBuf>
+}
+
+impl Default for Config
+{
+   fn default() -> Config
+   {
+      Config {
+         provider         : None,
+         diff             : "gvimdiff -f".to_string(),
+         diff_all         : None,
+         merge            : None,
+         exclude          : Vec::new(),
+         color            : ColorMode::Auto,
+         keep_going       : false,
+         resolve_trivial  : false,
+         policy           : Policy::Notify,
+         sqlite_dump      : false,
+         trash_dir        : None,
+         freedesktop_trash: false,
+         dedup_trash      : false,
+         retention        : None,
+         keys             : Keys::default(),
+         handlers         : BTreeMap::new(),
+         files            : Vec::new()
+      }
+   }
+}
+
+impl Config
+{
+   /// Reads the configuration of the user, the one of `start_dir` - if given - and the
+   /// environment variables.
+   pub fn load(start_dir: Option<&Path>) -> AppResult<Config>
+   {
+      let mut config = Config::default();
+      if let Some(file) = user_config_file() {
+         if file.is_file() {
+            config.read_file(&file, false)?;
+         }
+      }
+
+      if let Some(file) = start_dir.map(|dir| dir.join(FOLDER_CONFIG)) {
+         if file.is_file() {
+            config.read_file(&file, true)?;
+         }
+      }
+
+      if let Ok(diff) = env::var("CONFSOLVE_DIFF") {
+         tool::validate(&diff)
+            .map_err(|err| AppError::InvalidUsage(format!("Invalid command '{}' in CONFSOLVE_DIFF: {}!", diff, err)))?;
+
+         config.diff = diff;
+      }
+
+      if let Some(dir) = env::var_os("CONFSOLVE_TRASH_DIR") {
+         config.trash_dir = Some(PathBuf::from(dir));
+         config.freedesktop_trash = false;
+      }
+
+      if let Ok(retention) = env::var("CONFSOLVE_TRASH_RETENTION") {
+         if trash::parse_age(&retention).is_none() {
+            return Err(AppError::InvalidUsage(format!("Invalid age '{}' in CONFSOLVE_TRASH_RETENTION!", retention)));
+         }
+
+         config.retention = Some(retention);
+      }
+
+      Ok(config)
+   }
+
+   /// Sets the options, which haven't been given on the command line, by the configuration.
+   pub fn apply_to(&self, options: &mut Options)
+   {
+      let private_trash_given = options.trash_dir.is_some() || options.dedup_trash;
+      if self.freedesktop_trash && ! private_trash_given {
+         options.freedesktop_trash = true;
+      }
+
+      if ! options.freedesktop_trash {
+         options.trash_dir = options.trash_dir.take().or(self.trash_dir.clone());
+         options.dedup_trash = options.dedup_trash || self.dedup_trash;
+      }
+
+      options.keep_going = options.keep_going || self.keep_going;
+      options.resolve_trivial = options.resolve_trivial || self.resolve_trivial;
+      options.policy = options.policy.or(Some(self.policy));
+   }
+
+   /// If `file` inside of the start directory `start_dir` is excluded from resolving.
+   pub fn is_excluded(&self, start_dir: &Path, file: &Path) -> bool
+   {
+      let rel_path = file.strip_prefix(start_dir).unwrap_or(file);
+      self.exclude.iter().any(|pattern| {
+         pattern.matches_path(rel_path)
+            || rel_path.iter().any(|name| name.to_str().map(|n| pattern.matches(n)).unwrap_or(false))
+      })
+   }
+
+   fn read_file(&mut self, file: &Path, is_folder_config: bool) -> AppResult<()>
+   {
+      let text = fs::read_to_string(file).with_path(file)?;
+      for key in self.read_str(&text, file, is_folder_config)? {
+         writeln!(&mut io::stderr(), "Warning: Ignoring '{}' of '{}', it can only be set in the configuration of the user.",
+                  key, file.display())?;
+      }
+
+      Ok(())
+   }
+
+   // Reads the configuration `text` of the file `file`. Returns the keys which
+   // have been ignored, because they can't be set by a folder configuration.
+   fn read_str(&mut self, text: &str, file: &Path, is_folder_config: bool) -> AppResult<Vec<String>>
+   {
+      let invalid = |msg: String| AppError::InvalidUsage(format!("Invalid configuration file '{}': {}!", file.display(), msg));
+
+      let mut ignored = Vec::new();
+      let value = text.parse::<Value>().map_err(|err| invalid(err.to_string()))?;
+      let table = value.as_table().ok_or(invalid("not a table".to_string()))?;
+      for (key, value) in table.iter() {
+         match key.as_str() {
+            "provider" => {
+               self.provider = Some(match string(key, value).map_err(&invalid)? {
+                  "wuala"   => Wuala,
+                  "dropbox" => Dropbox,
+                  other     => return Err(invalid(format!("unknown provider '{}'", other)))
+               });
+            }
+
+            // the commands are executed, the trash directory could point to any directory of
+            // the user - whose files would then be removed by the retention of the trash -
+            // the policies remove files without asking and the excluded files are hidden
+            "diff" | "diff_all" | "merge" | "trash" | "policy" | "resolve_trivial" | "keep_going" | "exclude"
+               if is_folder_config => ignored.push(key.clone()),
+
+            "diff" | "diff_all" | "merge" => {
+               let cmd = string(key, value).map_err(&invalid)?;
+               tool::validate(cmd).map_err(|err| invalid(format!("invalid command '{}': {}", cmd, err)))?;
+               match key.as_str() {
+                  "diff"     => self.diff = cmd.to_string(),
+                  "diff_all" => self.diff_all = Some(cmd.to_string()),
+                  _          => self.merge = Some(cmd.to_string())
+               }
+            }
+
+            "exclude" => {
+               let patterns = value.as_array().ok_or(invalid("'exclude' isn't an array".to_string()))?;
+               for pattern in patterns.iter() {
+                  let pattern = string(key, pattern).map_err(&invalid)?;
+                  self.exclude.push(Pattern::new(pattern).map_err(|err| invalid(format!("invalid pattern '{}': {}", pattern, err)))?);
+               }
+            }
+
+            "color" => {
+               let mode = string(key, value).map_err(&invalid)?;
+               self.color = color::parse_color_mode(mode).ok_or(invalid(format!("unknown color mode '{}'", mode)))?;
+            }
+
+            "keep_going"      => self.keep_going = boolean(key, value).map_err(&invalid)?,
+            "resolve_trivial" => self.resolve_trivial = boolean(key, value).map_err(&invalid)?,
+            "sqlite_dump"     => self.sqlite_dump = boolean(key, value).map_err(&invalid)?,
+
+            "policy" => {
+               let policy = string(key, value).map_err(&invalid)?;
+               self.policy = watch::parse_policy(policy).ok_or(invalid(format!("unknown policy '{}'", policy)))?;
+            }
+
+            "trash" => self.read_trash(table_of(key, value).map_err(&invalid)?).map_err(&invalid)?,
+            "keys"  => self.read_keys(table_of(key, value).map_err(&invalid)?).map_err(&invalid)?,
+
+            "handlers" => {
+               for (extension, handler) in table_of(key, value).map_err(&invalid)?.iter() {
+                  let name = string(extension, handler).map_err(&invalid)?;
+                  let handler = compare::parse_handler(name).ok_or(invalid(format!("unknown handler '{}'", name)))?;
+                  self.handlers.insert(extension.to_lowercase(), handler);
+               }
+            }
+
+            _ => return Err(invalid(format!("unknown key '{}'", key)))
+         }
+      }
+
+      self.files.push(file.to_path_buf());
+      Ok(ignored)
+   }
+
+   fn read_trash(&mut self, table: &Table) -> Result<(), String>
+   {
+      for (key, value) in table.iter() {
+         match key.as_str() {
+            "dir"         => self.trash_dir = Some(PathBuf::from(string(key, value)?)),
+            "freedesktop" => self.freedesktop_trash = boolean(key, value)?,
+            "dedup"       => self.dedup_trash = boolean(key, value)?,
+            "retention"   => {
+               let retention = string(key, value)?;
+               if trash::parse_age(retention).is_none() {
+                  return Err(format!("invalid age '{}'", retention));
+               }
+
+               self.retention = Some(retention.to_string());
+            }
+
+            _ => return Err(format!("unknown key 'trash.{}'", key))
+         }
+      }
+
+      if self.freedesktop_trash && (self.trash_dir.is_some() || self.dedup_trash) {
+         return Err("'trash.freedesktop' can't be combined with 'trash.dir' or 'trash.dedup'".to_string());
+      }
+
+      Ok(())
+   }
+
+   fn read_keys(&mut self, table: &Table) -> Result<(), String>
+   {
+      for (name, value) in table.iter() {
+         let key = string(name, value)?;
+         let mut chars = key.chars();
+         let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            _               => return Err(format!("the key '{}' for '{}' isn't a single character", key, name))
+         };
+
+         match name.as_str() {
+            "take"    => self.keys.take = key,
+            "trash"   => self.keys.trash = key,
+            "diff"    => self.keys.diff = key,
+            "merge"   => self.keys.merge = key,
+            "combine" => self.keys.combine = key,
+            "skip"    => self.keys.skip = key,
+            "quit"    => self.keys.quit = key,
+            "help"    => self.keys.help = key,
+            _         => return Err(format!("unknown key 'keys.{}'", name))
+         }
+      }
+
+      self.keys.validate()
+   }
+}
+
+// Formats the configuration as a TOML configuration file.
+impl Display for Config
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      let toml_str = |s: &str| Value::String(s.to_string()).to_string();
+
+      if self.files.is_empty() {
+         writeln!(f, "# no configuration files read")?;
+      }
+
+      for file in self.files.iter() {
+         writeln!(f, "# read from '{}'", file.display())?;
+      }
+
+      match self.provider {
+         Some(provider) => writeln!(f, "provider = {}", toml_str(&provider.to_string()))?,
+         None           => writeln!(f, "# provider = \"dropbox\"")?
+      }
+
+      writeln!(f, "diff = {}", toml_str(&self.diff))?;
+      match self.diff_all {
+         Some(ref diff_all) => writeln!(f, "diff_all = {}", toml_str(diff_all))?,
+         None               => writeln!(f, "# diff_all = \"vimdiff\"")?
+      }
+
+      match self.merge {
+         Some(ref merge) => writeln!(f, "merge = {}", toml_str(merge))?,
+         None            => writeln!(f, "# merge = \"meld\"")?
+      }
+
+      let exclude = self.exclude.iter().map(|p| toml_str(p.as_str())).collect::<Vec<String>>();
+      writeln!(f, "exclude = [{}]", exclude.join(", "))?;
+      writeln!(f, "color = {}", toml_str(&self.color.to_string()))?;
+      writeln!(f, "keep_going = {}", self.keep_going)?;
+      writeln!(f, "resolve_trivial = {}", self.resolve_trivial)?;
+      writeln!(f, "policy = {}", toml_str(&self.policy.to_string()))?;
+      writeln!(f, "sqlite_dump = {}", self.sqlite_dump)?;
+
+      writeln!(f, "\n[trash]")?;
+      match self.trash_dir {
+         Some(ref dir) => writeln!(f, "dir = {}", toml_str(&dir.to_string_lossy()))?,
+         None          => writeln!(f, "# dir = \"/path/to/trash\"")?
+      }
+
+      writeln!(f, "freedesktop = {}", self.freedesktop_trash)?;
+      writeln!(f, "dedup = {}", self.dedup_trash)?;
+      match self.retention {
+         Some(ref retention) => writeln!(f, "retention = {}", toml_str(retention))?,
+         None                => writeln!(f, "# retention = \"30d\"")?
+      }
+
+      writeln!(f, "\n[keys]")?;
+      for (name, key) in self.keys.all().iter() {
+         writeln!(f, "{} = {}", name, toml_str(&key.to_string()))?;
+      }
+
+      writeln!(f, "\n[handlers]")?;
+      if self.handlers.is_empty() {
+         writeln!(f, "# svg = \"text\"")?;
+      }
+
+      for (extension, handler) in self.handlers.iter() {
+         writeln!(f, "{} = {}", toml_str(extension), toml_str(&handler.to_string()))?;
+      }
+
+      Ok(())
+   }
+}
+
+/// The configuration file of the user.
+pub fn user_config_file() -> Option<PathBuf>
+{
+   appdirs::config_home().map(|dir| dir.join("confsolve").join("config.toml"))
+}
+
+fn string<'a>(key: &str, value: &'a Value) -> Result<&'a str, String>
+{
+   value.as_str().ok_or(format!("'{}' isn't a string", key))
+}
+
+fn boolean(key: &str, value: &Value) -> Result<bool, String>
+{
+   value.as_bool().ok_or(format!("'{}' isn't a boolean", key))
+}
+
+fn table_of<'a>(key: &str, value: &'a Value) -> Result<&'a Table, String>
+{
+   value.as_table().ok_or(format!("'{}' isn't a table", key))
+}
+
+#[test]
+fn tests()
+{
+   let file = Path::new("config.toml");
+   let mut config = Config::default();
+   config.read_str("provider = \"wuala\"\nexclude = [\"*.tmp\"]\n[trash]\ndedup = true\n[keys]\ntake = \"u\"\n[handlers]\nSVG = \"text\"\n",
+                   file, false).unwrap();
+   assert_eq!(config.provider, Some(Wuala));
+   assert!(config.dedup_trash);
+   assert_eq!(config.keys.take, 'u');
+   assert_eq!(config.handlers.get("svg"), Some(&Handler::Text));
+   assert!(config.is_excluded(Path::new("/dir"), Path::new("/dir/a/b.tmp")));
+   assert!(! config.is_excluded(Path::new("/dir"), Path::new("/dir/a/b.txt")));
+
+   // the configuration printed by 'config show' can be read again
+   let mut reread = Config::default();
+   assert!(reread.read_str(&config.to_string(), file, false).unwrap().is_empty());
+   assert_eq!(reread.to_string(), config.to_string());
+
+   assert!(Config::default().read_str("blub = 1\n", file, false).is_err());
+
+   // a folder configuration can't set what removes or hides files
+   let mut folder_config = Config::default();
+   let ignored = folder_config.read_str("diff = \"rm -rf ~\"\nexclude = [\"*\"]\npolicy = \"take-newest\"\nresolve_trivial = true\n\
+                                         keep_going = true\ncolor = \"never\"\n[trash]\ndir = \"/home\"\nretention = \"1s\"\n",
+                                         file, true).unwrap();
+   assert_eq!(ignored, ["diff", "exclude", "policy", "resolve_trivial", "keep_going", "trash"]);
+   assert_eq!(folder_config.diff, Config::default().diff);
+   assert!(folder_config.exclude.is_empty());
+   assert_eq!(folder_config.policy, Policy::Notify);
+   assert!(! folder_config.resolve_trivial && ! folder_config.keep_going);
+   assert_eq!(folder_config.trash_dir, None);
+   assert_eq!(folder_config.retention, None);
+   assert_eq!(folder_config.color, ColorMode::Never);
+
+   assert!(Config::default().read_str("[keys]\ntake = \"s\"\n", file, false).is_err());
+
+   let mut options = Options { trash_dir: Some(PathBuf::from("cli")), ..Options::default() };
+   Config { freedesktop_trash: true, keep_going: true, ..Config::default() }.apply_to(&mut options);
+   assert!(! options.freedesktop_trash);
+   assert!(options.keep_going);
+   assert_eq!(options.policy, Some(Policy::Notify));
+}
@@ -0,0 +1,179 @@
+use std::fmt::{Display, Formatter, Error};
+use parser::{Parser, ParseError};
+
+/// A local date and time with a precision of seconds.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct DateTime
+{
+   pub year  : i32,
+   pub month : u32,
+   pub day   : u32,
+   pub hour  : u32,
+   pub minute: u32,
+   pub second: u32
+}
+
+impl DateTime
+{
+   /// The current local date and time.
+   pub fn now() -> DateTime
+   {
+      now_internal()
+   }
+
+   /// The name of a directory for the date and time, in the form
+   /// `YYYY-MM-DD_hh-mm-ss`, which sorts chronologically and is
+   /// a valid file name on every platform.
+   pub fn dir_name(&self) -> String
+   {
+      format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+              self.year, self.month, self.day, self.hour, self.minute, self.second)
+   }
+
+   /// Parses a date and time in the form of `dir_name`.
+   pub fn from_dir_name(name: &str) -> Option<DateTime>
+   {
+      parse(name, '_', '-').ok()
+   }
+
+   /// The number of seconds from `self` to the later date and time `other`.
+   pub fn seconds_until(&self, other: &DateTime) -> i64
+   {
+      other.seconds() - self.seconds()
+   }
+
+   /// The seconds since 1970-01-01T00:00:00, ignoring time zones.
+   pub fn seconds(&self) -> i64
+   {
+      // the days from civil algorithm of http://howardhinnant.github.io/date_algorithms.html
+      let year = if self.month <= 2 { self.year - 1 } else { self.year } as i64;
+      let era = year.div_euclid(400);
+      let yoe = year.rem_euclid(400);
+      let month = self.month as i64;
+      let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+      let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+      let days = era * 146097 + doe - 719468;
+
+      days * 86400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+   }
+}
+
+// Parses `YYYY-MM-DD<date_sep>hh<time_sep>mm<time_sep>ss`.
+fn parse(input: &str, date_sep: char, time_sep: char) -> Result<DateTime, ParseError>
+{
+   let mut parser = Parser::new(input);
+   let year = parser.take_uint()?;
+   parser.skip("-")?;
+   let month = parser.take_uint()?;
+   parser.skip("-")?;
+   let day = parser.take_uint()?;
+   parser.skip(&date_sep.to_string())?;
+   let hour = parser.take_uint()?;
+   parser.skip(&time_sep.to_string())?;
+   let minute = parser.take_uint()?;
+   parser.skip(&time_sep.to_string())?;
+   let second = parser.take_uint()?;
+
+   if ! parser.eof() {
+      return Err(format!("Unexpected input after date and time: '{}'!", parser.take_till_eof()));
+   }
+
+   if ! (1..=12).contains(&month) || ! (1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+      return Err(format!("Invalid date and time: '{}'!", input));
+   }
+
+   Ok(DateTime {
+      year  : year as i32,
+      month : month as u32,
+      day   : day as u32,
+      hour  : hour as u32,
+      minute: minute as u32,
+      second: second as u32
+   })
+}
+
+// Formats the date and time in the form `YYYY-MM-DDThh:mm:ss`.
+impl Display for DateTime
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+             self.year, self.month, self.day, self.hour, self.minute, self.second)
+   }
+}
+
+#[cfg(unix)]
+fn now_internal() -> DateTime
+{
+   use std::{mem, ptr};
+   use libc;
+
+   let tm = unsafe {
+      let now = libc::time(ptr::null_mut());
+      let mut tm: libc::tm = mem::zeroed();
+      libc::localtime_r(&now, &mut tm);
+      tm
+   };
+
+   DateTime {
+      year  : tm.tm_year + 1900,
+      month : (tm.tm_mon + 1) as u32,
+      day   : tm.tm_mday as u32,
+      hour  : tm.tm_hour as u32,
+      minute: tm.tm_min as u32,
+      second: tm.tm_sec as u32
+   }
+}
+
+// Without libc there's no portable way to get the local time zone, so UTC is used.
+#[cfg(not(unix))]
+fn now_internal() -> DateTime
+{
+   use std::time::{SystemTime, UNIX_EPOCH};
+
+   let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+   let days = secs.div_euclid(86400);
+   let secs_of_day = secs.rem_euclid(86400) as u32;
+
+   // the days to civil algorithm of http://howardhinnant.github.io/date_algorithms.html
+   let z = days + 719468;
+   let era = z.div_euclid(146097);
+   let doe = z.rem_euclid(146097);
+   let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+   let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+   let mp = (5 * doy + 2) / 153;
+   let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+   let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+   let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+
+   DateTime {
+      year, month, day,
+      hour  : secs_of_day / 3600,
+      minute: secs_of_day % 3600 / 60,
+      second: secs_of_day % 60
+   }
+}
+
+#[test]
+fn tests()
+{
+   let date_time = DateTime { year: 2004, month: 8, day: 31, hour: 2, minute: 3, second: 8 };
+   assert_eq!(format!("{}", date_time), "2004-08-31T02:03:08");
+   assert_eq!(date_time.dir_name(), "2004-08-31_02-03-08");
+   assert_eq!(DateTime::from_dir_name("2004-08-31_02-03-08"), Some(date_time));
+   assert_eq!(DateTime::from_dir_name("2004-08-31_02-03-08-blub"), None);
+   assert_eq!(DateTime::from_dir_name("2004-13-31_02-03-08"), None);
+   assert_eq!(DateTime::from_dir_name("blub"), None);
+
+   let later = DateTime { year: 2004, month: 9, day: 1, hour: 2, minute: 3, second: 9 };
+   assert_eq!(date_time.seconds_until(&later), 86400 + 1);
+   assert_eq!(later.seconds_until(&date_time), -86400 - 1);
+
+   let epoch = DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+   assert_eq!(epoch.seconds(), 0);
+   assert_eq!(DateTime { year: 2000, month: 3, day: 1, hour: 0, minute: 0, second: 0 }.seconds(), 951868800);
+
+   let now = DateTime::now();
+   assert!(now.year >= 2015);
+   assert!(now.month >= 1 && now.month <= 12);
+}
@@ -3,12 +3,17 @@ use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::vec::Vec;
 use std::path::{Path, PathBuf};
 
+use std::fs::read_dir;
+
 use file_system::walk_files;
-use app_result::AppResult;
+use scan_index;
+use lock::LOCK_FILE;
+use app_result::{AppResult, WithPath};
 
 use super::types::{
    Conflict,
    ConflictingFile,
+   Scan,
    ConflictType,
    Wuala,
    Dropbox,
@@ -20,27 +25,35 @@ use super::wuala;
 use super::dropbox;
 
 /// Finds all conflicts of type `conf_type` in the directory hierarchy starting at `start_dir`.
-pub fn find(conf_type: ConflictType, start_dir: &Path) -> AppResult<Vec<Conflict>>
+/// Subdirectories which couldn't be read are reported in the warnings of the returned `Scan`.
+/// With `use_index` only the directories changed since the last scan are read.
+/// Conflicts of the lock files of confsolve are skipped.
+pub fn find(conf_type: ConflictType, start_dir: &Path, use_index: bool) -> AppResult<Scan>
 {
-   let parse: fn(&str) -> Option<(OrigFileName, Details)> = match conf_type {
-      Wuala   => wuala::parse,
-      Dropbox => dropbox::parse
+   let parse = parse_fn(conf_type);
+   let (files, warnings) = if use_index {
+      scan_index::walk(start_dir)?
+   } else {
+      let mut files = walk_files(start_dir)?;
+      (files.by_ref().collect(), files.into_warnings())
    };
 
-   let files = walk_files(start_dir)?;
    let mut confs_by_orig: HashMap<PathBuf, Vec<ConflictingFile>> = HashMap::new();
    for file in files {
-      file.file_name().and_then(|s| s.to_str()).map(|filename| {
-         parse(filename).map(|(orig, details)| {
-            let mut orig_file = file.clone();
-            orig_file.set_file_name(&orig);
-            let conf = ConflictingFile {details: details, path: file.clone()};
-            match confs_by_orig.entry(orig_file) {
-               Occupied(mut entry) => entry.get_mut().push(conf),
-               Vacant(entry)       => { entry.insert(vec![conf]); }
-            }
-         });
-      });
+      let filename = match file.file_name().and_then(|s| s.to_str()) {
+         Some(filename) => filename,
+         None           => continue
+      };
+
+      if let Some((orig, details)) = parse(filename).filter(|(orig, _)| orig != LOCK_FILE) {
+         let mut orig_file = file.clone();
+         orig_file.set_file_name(&orig);
+         let conf = ConflictingFile {details, path: file.clone()};
+         match confs_by_orig.entry(orig_file) {
+            Occupied(mut entry) => entry.get_mut().push(conf),
+            Vacant(entry)       => { entry.insert(vec![conf]); }
+         }
+      }
    }
 
    let mut confs = Vec::new();
@@ -48,5 +61,49 @@ pub fn find(conf_type: ConflictType, start_dir: &Path) -> AppResult<Vec<Conflict
       confs.push(Conflict {original_path: orig, conflicting_files: conf});
    }
 
-   Ok(confs)
+   Ok(Scan {conflicts: confs, warnings})
+}
+
+/// Returns the conflict of type `conf_type` the file `file` belongs to, with all
+/// conflicting files of the same original file inside of the directory of `file`.
+/// Returns none if `file` isn't a conflicting file, or if it's one of a lock file.
+pub fn conflict_of(conf_type: ConflictType, file: &Path) -> AppResult<Option<Conflict>>
+{
+   let parse = parse_fn(conf_type);
+   let orig = match file.file_name().and_then(|s| s.to_str()).and_then(parse) {
+      Some((ref orig, _)) if orig == LOCK_FILE => return Ok(None),
+      Some((orig, _))                          => orig,
+      None                                     => return Ok(None)
+   };
+
+   let dir = match file.parent() {
+      Some(dir) => dir,
+      None      => return Ok(None)
+   };
+
+   let mut conf_files = Vec::new();
+   for entry in read_dir(dir).with_path(dir)? {
+      let path = entry.with_path(dir)?.path();
+      let parsed = path.file_name().and_then(|s| s.to_str()).and_then(parse);
+      if let Some((entry_orig, details)) = parsed {
+         if entry_orig == orig && path.is_file() {
+            conf_files.push(ConflictingFile {details, path});
+         }
+      }
+   }
+
+   if conf_files.is_empty() {
+      return Ok(None);
+   }
+
+   conf_files.sort_by(|f1, f2| f1.path.cmp(&f2.path));
+   Ok(Some(Conflict {original_path: dir.join(orig), conflicting_files: conf_files}))
+}
+
+fn parse_fn(conf_type: ConflictType) -> fn(&str) -> Option<(OrigFileName, Details)>
+{
+   match conf_type {
+      Wuala   => wuala::parse,
+      Dropbox => dropbox::parse
+   }
 }
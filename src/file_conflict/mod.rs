@@ -1,12 +1,12 @@
 pub use self::types::{
+   Scan,
+   Conflict,
    ConflictType,
    Wuala,
-   Dropbox,
-   Conflict,
-   ConflictingFile
+   Dropbox
 };
 
-pub use self::find::find;
+pub use self::find::{find, conflict_of};
 
 pub mod types;
 pub mod find;
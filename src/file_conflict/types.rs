@@ -1,5 +1,6 @@
 use std::fmt::{Display, Formatter, Error};
 use std::path::PathBuf;
+use file_system::{ScanWarning, FileState};
 
 pub use self::ConflictType::{
    Wuala,
@@ -7,12 +8,24 @@ pub use self::ConflictType::{
 };
 
 /// The kind of conflicts to search for and to resolve.
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum ConflictType
 {
    Wuala,
    Dropbox
 }
 
+impl Display for ConflictType
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Wuala   => write!(f, "wuala"),
+         Dropbox => write!(f, "dropbox")
+      }
+   }
+}
+
 // the file name of the original file,
 // without the details of the conflict
 pub type OrigFileName = String;
@@ -35,6 +48,25 @@ pub struct Conflict
    pub conflicting_files:  Vec<ConflictingFile>
 }
 
+// all conflicts found by a scan and the parts
+// of the directory hierarchy which couldn't be scanned
+pub struct Scan
+{
+   pub conflicts:  Vec<Conflict>,
+   pub warnings :  Vec<ScanWarning>
+}
+
+impl Conflict
+{
+   /// Records the current state of the original file and of all conflicting files.
+   pub fn file_states(&self) -> Vec<FileState>
+   {
+      let mut states = vec![FileState::of(&self.original_path)];
+      states.extend(self.conflicting_files.iter().map(|f| FileState::of(&f.path)));
+      states
+   }
+}
+
 impl Display for ConflictingFile
 {
    fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
@@ -1,11 +1,22 @@
+use std::io;
+use std::env;
 use std::iter::Iterator;
 use std::path::{Path, PathBuf};
 use std::vec::Vec;
-use app_result::{AppResult, AppError};
+use std::process;
+use std::hash::{BuildHasher, Hasher};
+use std::collections::hash_map::RandomState;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt::{Display, Formatter, Error};
+use sha2::{Sha256, Digest};
+use app_result::{AppResult, AppError, WithPath};
 use appdirs;
 
 use std::fs::{
+   self,
+   File,
    read_dir,
+   ReadDir,
    create_dir_all,
    remove_file,
    copy
@@ -13,55 +24,106 @@ use std::fs::{
 
 /// Returns an iterator which will recursively walk the files starting at
 /// `start_dir`. This will perform iteration in some top-down order.
-/// The contents of unreadable subdirectories are ignored.
+/// The contents of unreadable subdirectories are skipped and recorded
+/// as `ScanWarning`s, which can be taken by `Files::into_warnings`.
 pub fn walk_files(start_dir: &Path) -> AppResult<Files>
 {
-   match read_dir(start_dir) {
-      Ok(contents) => {
-         let paths = contents
-            .filter_map(|c| c.ok().map(|e| e.path()))
-            .collect::<Vec<PathBuf>>();
+   let contents = read_dir(start_dir).with_path(start_dir)?;
+   let mut files = Files::new(Vec::new());
+   files.push_dir_contents(start_dir, contents);
+   Ok(files)
+}
 
-         Ok(Files::new(paths))
-      }
+/// A part of the directory hierarchy which couldn't be scanned.
+pub struct ScanWarning
+{
+   pub path : PathBuf,
+   pub kind : io::ErrorKind,
+   pub error: String
+}
 
-      Err(err) => Err(AppError::from_string(format!("{}", err)))
+impl ScanWarning
+{
+   pub fn new(path: &Path, err: &io::Error) -> ScanWarning
+   {
+      ScanWarning { path: path.to_path_buf(), kind: err.kind(), error: format!("{}", err) }
    }
 }
 
-/// Moves `file` into the trash directory of confsolve.
-pub fn move_to_trash(file: &Path) -> AppResult<()>
+impl Display for ScanWarning
 {
-   let filename = file.file_name()
-       .ok_or(AppError::from_string(format!("Couldn't get filename from path '{}'!", file.display())))?;
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      write!(f, "Couldn't scan '{}' ({:?}): {}", self.path.display(), self.kind, self.error)
+   }
+}
 
-   let mut trash_file = trash_dir()?;
-   trash_file.push(filename);
-   let trash_file = unique_file(&trash_file)?;
+/// The size and the modification time of a file at some point in time, used
+/// to detect changes of the file, e.g. by a still running synchronization.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FileState
+{
+   pub path: PathBuf,
 
-   copy(file, &trash_file)?;
-   remove_file(file)?;
+   // the size and the modification time, none if the file doesn't exist
+   state: Option<(u64, SystemTime)>
+}
 
-   Ok(())
+impl FileState
+{
+   /// Records the current state of `file`.
+   pub fn of(file: &Path) -> FileState
+   {
+      let state = fs::metadata(file).ok()
+         .filter(|meta| meta.is_file())
+         .map(|meta| (meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+
+      FileState { path: file.to_path_buf(), state }
+   }
+
+   /// If the file has been changed, created or removed since its state has been recorded.
+   pub fn has_changed(&self) -> bool
+   {
+      FileState::of(&self.path) != *self
+   }
 }
 
 pub fn move_file(from_file: &Path, to_file: &Path) -> AppResult<()>
 {
-   copy(from_file, to_file)?;
-   remove_file(from_file)?;
+   copy(from_file, to_file).with_path(from_file)?;
+   remove_file(from_file).with_path(from_file)?;
 
    Ok(())
 }
 
+/// Returns the SHA-256 hash of the contents of `file` as a lowercase hex string.
+pub fn hash_file(file: &Path) -> AppResult<String>
+{
+   let mut hasher = Sha256::new();
+   io::copy(&mut File::open(file).with_path(file)?, &mut hasher).with_path(file)?;
+   Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
 /// Returns the trash directory of confsolve, where all deleted/moved files are put into.
-pub fn trash_dir() -> AppResult<PathBuf>
+///
+/// The directory is - in this order - `custom_dir`, the directory specified by the
+/// environment variable `CONFSOLVE_TRASH_DIR` or the directory `trash` inside of
+/// the cache directory of confsolve. It's created if it doesn't exist yet.
+pub fn trash_dir(custom_dir: Option<&Path>) -> AppResult<PathBuf>
 {
-   let mut dir = appdirs::cache("confsolve")
-      .ok_or(AppError::from_string(format!("Couldn't get cache directory!")))?;
+   let dir = match custom_dir.map(|d| d.to_path_buf()).or(env::var_os("CONFSOLVE_TRASH_DIR").map(PathBuf::from)) {
+      Some(dir) => dir,
+      None      => {
+         let mut dir = appdirs::cache("confsolve")
+            .ok_or(AppError::from_string("Couldn't get cache directory!".to_string()))?;
+
+         dir.push("trash");
+         dir
+      }
+   };
 
-   dir.push("trash");
    if ! dir.is_dir() {
-      create_dir_all(&dir)?;
+      create_dir_all(&dir).with_path(&dir)?;
    }
 
    Ok(dir)
@@ -81,7 +143,7 @@ pub fn unique_file(file: &Path) -> AppResult<PathBuf>
       .ok_or(AppError::from_string(format!("Couldn't get filename_str of '{}'!", file_buf.display())))?;
 
    for i in 2..10000 {
-      file_buf.set_file_name(&format!("{}-{}", filename_str, i));
+      file_buf.set_file_name(format!("{}-{}", filename_str, i));
       if ! file_buf.is_file() {
          return Ok(file_buf);
       }
@@ -90,20 +152,102 @@ pub fn unique_file(file: &Path) -> AppResult<PathBuf>
    Err(AppError::from_string(format!("Couldn't get a unique path for '{}'!", file_buf.display())))
 }
 
+/// Creates a new directory inside of the temporary directory, with a random name and only
+/// accessible by the user. A directory or a symlink created in the shared temporary
+/// directory by someone else is never used, instead another name is tried.
+pub fn create_temp_dir() -> AppResult<PathBuf>
+{
+   let mut builder = fs::DirBuilder::new();
+   #[cfg(unix)]
+   {
+      use std::os::unix::fs::DirBuilderExt;
+      builder.mode(0o700);
+   }
+
+   loop {
+      let dir = env::temp_dir().join(format!("confsolve-{}", random_suffix()));
+      match builder.create(&dir) {
+         Ok(())                                                     => return Ok(dir),
+         Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+         Err(err)                                                   => return Err(err).with_path(&dir)
+      }
+   }
+}
+
+/// A random suffix for the name of a file, which doesn't collide with
+/// the ones of other processes or of former calls.
+pub fn random_suffix() -> String
+{
+   let mut hasher = RandomState::new().build_hasher();
+   hasher.write_u32(process::id());
+   hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+   format!("{}-{:016x}", process::id(), hasher.finish())
+}
+
+/// Escapes the backslash and the line endings of `path`, so that it fits into one line.
+pub fn escape_line(path: &str) -> String
+{
+   path.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+/// Reverses `escape_line`.
+pub fn unescape_line(path: &str) -> String
+{
+   let mut unescaped = String::new();
+   let mut chars = path.chars();
+   while let Some(c) = chars.next() {
+      if c != '\\' {
+         unescaped.push(c);
+         continue;
+      }
+
+      match chars.next() {
+         Some('n') => unescaped.push('\n'),
+         Some('r') => unescaped.push('\r'),
+         Some(c)   => unescaped.push(c),
+         None      => unescaped.push('\\')
+      }
+   }
+
+   unescaped
+}
+
 /// An iterator which walks over Files
 pub struct Files
 {
    stack: Vec<PathBuf>,
+   warnings: Vec<ScanWarning>
 }
 
 impl Files
 {
    fn new(paths: Vec<PathBuf>) -> Files
    {
-      let mut files = Files {stack: Vec::with_capacity(10_000)};
-      files.stack.extend(paths.into_iter());
+      let mut files = Files {stack: Vec::with_capacity(10_000), warnings: Vec::new()};
+      files.stack.extend(paths);
       files
    }
+
+   /// Consumes the iterator and returns the collected warnings.
+   pub fn into_warnings(self) -> Vec<ScanWarning>
+   {
+      self.warnings
+   }
+
+   fn push_dir_contents(&mut self, dir: &Path, contents: ReadDir)
+   {
+      let (size, _) = contents.size_hint();
+      if self.stack.capacity() < self.stack.len() + size {
+         self.stack.reserve(size);
+      }
+
+      for entry in contents {
+         match entry {
+            Ok(entry) => self.stack.push(entry.path()),
+            Err(err)  => self.warnings.push(ScanWarning::new(dir, &err))
+         }
+      }
+   }
 }
 
 impl Iterator for Files
@@ -121,17 +265,8 @@ impl Iterator for Files
 
                if path.is_dir() {
                   match read_dir(&path) {
-                     Err(..) => {}
-
-                     Ok(contents) => {
-                        let (size, _) = contents.size_hint();
-                        if self.stack.capacity() < self.stack.len() + size {
-                           self.stack.reserve(size);
-                        }
-
-                        let paths = contents.filter_map(|c| c.ok().map(|e| e.path()));
-                        self.stack.extend(paths);
-                     }
+                     Err(err)     => self.warnings.push(ScanWarning::new(&path, &err)),
+                     Ok(contents) => self.push_dir_contents(&path, contents)
                   }
                }
             }
@@ -141,3 +276,21 @@ impl Iterator for Files
       None
    }
 }
+
+#[test]
+fn tests()
+{
+   assert_eq!(escape_line("a/b\nc\\d"), "a/b\\nc\\\\d");
+   assert_eq!(unescape_line(&escape_line("a/b\nc\\d\r")), "a/b\nc\\d\r");
+
+   let (dir1, dir2) = (create_temp_dir().unwrap(), create_temp_dir().unwrap());
+   assert!(dir1 != dir2 && dir1.is_dir() && dir2.is_dir());
+   #[cfg(unix)]
+   {
+      use std::os::unix::fs::PermissionsExt;
+      assert_eq!(fs::metadata(&dir1).unwrap().permissions().mode() & 0o777, 0o700);
+   }
+
+   let _ = fs::remove_dir(&dir1);
+   let _ = fs::remove_dir(&dir2);
+}
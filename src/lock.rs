@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::fs::{self, OpenOptions};
+use std::fmt::{Display, Formatter, Error};
+use std::process;
+
+use app_result::{AppResult, AppError, WithPath};
+use date_time::DateTime;
+use session_log::{user_name, host_name};
+
+/// The name of the lock file inside of the start directory.
+pub const LOCK_FILE: &str = ".confsolve.lock";
+
+/// An advisory lock of a start directory, preventing two runs of confsolve
+/// from resolving the conflicts of the same directory at the same time.
+///
+/// The lock is the file `.confsolve.lock` inside of the start directory, so that
+/// it's also seen by the other members of a shared folder. The lock files of the
+/// parent directories are checked too, because a run for a parent directory also
+/// resolves the conflicts of its subdirectories. It contains one line describing
+/// the holder of the lock:
+///
+///    `<user>@<host>\t<pid>\t<date time>`
+///
+/// The lock is released when it's dropped.
+pub struct Lock
+{
+   file  : PathBuf,
+   holder: Holder
+}
+
+/// Who holds a lock.
+#[derive(PartialEq, Debug)]
+pub struct Holder
+{
+   pub user : String,
+   pub host : String,
+   pub pid  : u32,
+   pub since: String
+}
+
+impl Lock
+{
+   /// Acquires the lock of `start_dir`. Fails if another run of confsolve holds
+   /// the lock - or the lock of a parent directory - unless it's a stale lock of a
+   /// process of this host which doesn't run anymore. With `force` a lock held by
+   /// another run is taken over, and the locks of the parent directories are ignored.
+   pub fn acquire(start_dir: &Path, force: bool) -> AppResult<Lock>
+   {
+      let file = start_dir.join(LOCK_FILE);
+      let holder = Holder {
+         user : user_name(),
+         host : host_name(),
+         pid  : process::id(),
+         since: DateTime::now().to_string()
+      };
+
+      if ! force {
+         let start_dir = fs::canonicalize(start_dir).with_path(start_dir)?;
+         for dir in start_dir.ancestors().skip(1) {
+            if let Some(other) = read_holder(&dir.join(LOCK_FILE)).filter(|o| ! o.is_stale(&holder)) {
+               return Err(AppError::Locked { dir: dir.to_path_buf(), holder: other.to_string() });
+            }
+         }
+      }
+
+      // the second try is after removing a stale or forced lock
+      for _ in 0..2 {
+         match OpenOptions::new().write(true).create_new(true).open(&file) {
+            Ok(mut handle) => {
+               handle.write_all(holder.to_line().as_bytes()).with_path(&file)?;
+               return Ok(Lock { file, holder });
+            }
+
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+               let other = read_holder(&file);
+               let is_stale = other.as_ref().map(|o| o.is_stale(&holder)).unwrap_or(false);
+               if ! force && ! is_stale {
+                  return Err(AppError::Locked {
+                     dir: start_dir.to_path_buf(),
+                     holder: other.map(|o| o.to_string()).unwrap_or("an unknown holder".to_string())
+                  });
+               }
+
+               fs::remove_file(&file).with_path(&file)?;
+            }
+
+            Err(err) => return Err(AppError::io(err, &file))
+         }
+      }
+
+      Err(AppError::from_string(format!("Couldn't acquire the lock '{}'!", file.display())))
+   }
+}
+
+impl Drop for Lock
+{
+   fn drop(&mut self)
+   {
+      // the lock might have been taken over by another run with '--force'
+      let is_own_lock = fs::read_to_string(&self.file)
+         .map(|line| line == self.holder.to_line())
+         .unwrap_or(false);
+
+      if is_own_lock {
+         let _ = fs::remove_file(&self.file);
+      }
+   }
+}
+
+impl Holder
+{
+   // If the holder is a process of the host of `own` which doesn't run anymore.
+   fn is_stale(&self, own: &Holder) -> bool
+   {
+      self.host == own.host && ! process_exists(self.pid)
+   }
+
+   fn to_line(&self) -> String
+   {
+      format!("{}@{}\t{}\t{}\n", self.user, self.host, self.pid, self.since)
+   }
+
+   fn from_line(line: &str) -> Option<Holder>
+   {
+      let mut fields = line.trim_end().splitn(3, '\t');
+      let who = fields.next()?;
+      let pid = fields.next()?.parse::<u32>().ok()?;
+      let since = fields.next()?;
+      let at = who.rfind('@')?;
+      Some(Holder {
+         user : who[..at].to_string(),
+         host : who[at + 1..].to_string(),
+         pid,
+         since: since.to_string()
+      })
+   }
+}
+
+impl Display for Holder
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      write!(f, "{}@{} (pid {}) since {}", self.user, self.host, self.pid, self.since)
+   }
+}
+
+// The holder of the lock file `file`, none if there's no lock or it's unreadable.
+fn read_holder(file: &Path) -> Option<Holder>
+{
+   fs::read_to_string(file).ok().and_then(|line| Holder::from_line(&line))
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool
+{
+   use libc;
+
+   if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+      return true;
+   }
+
+   // the process exists, but belongs to another user
+   io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a way to check for the process, every lock is considered as held.
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool
+{
+   true
+}
+
+#[test]
+fn tests()
+{
+   let holder = Holder { user: "dan".to_string(), host: "box".to_string(), pid: 42, since: "2015-06-01T14:23:05".to_string() };
+   assert_eq!(holder.to_line(), "dan@box\t42\t2015-06-01T14:23:05\n");
+   assert_eq!(Holder::from_line(&holder.to_line()), Some(holder));
+   assert_eq!(Holder::from_line("dan@box\tx\t2015"), None);
+   assert_eq!(Holder::from_line("dan\t42\t2015"), None);
+   assert!(process_exists(process::id()));
+
+   let start_dir = ::std::env::temp_dir().join(format!("confsolve-lock-test-{}", process::id()));
+   fs::create_dir_all(start_dir.join("sub")).unwrap();
+   {
+      let lock = Lock::acquire(&start_dir, false).unwrap();
+      assert_eq!(lock.file, start_dir.join(LOCK_FILE));
+      match Lock::acquire(&start_dir.join("sub").join(".."), false) {
+         Err(AppError::Locked { .. }) => (),
+         _                            => panic!("The lock has been acquired twice!")
+      }
+
+      match Lock::acquire(&start_dir.join("sub"), false) {
+         Err(AppError::Locked { ref dir, .. }) if *dir == fs::canonicalize(&start_dir).unwrap() => (),
+         _                                                                                     => panic!("The lock of the parent directory has been ignored!")
+      }
+   }
+
+   drop(Lock::acquire(&start_dir.join("sub"), false).unwrap());
+
+   let lock = Lock::acquire(&start_dir, false).unwrap();
+   drop(lock);
+   let _ = fs::remove_dir_all(&start_dir);
+}
@@ -1,13 +1,24 @@
 #![cfg_attr(test, allow(dead_code))]
 
 extern crate dirs;
+extern crate sha2;
+extern crate toml;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate rusqlite;
+extern crate zip;
+extern crate glob;
+#[cfg(unix)]
+extern crate libc;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::fs;
 use std::io::{self, Write};
 use std::process::{self, Command};
-use std::env;
 
 use file_conflict::{
+   Scan,
+   Conflict,
    ConflictType,
    Wuala,
    Dropbox
@@ -19,29 +30,73 @@ use user_reply::UserReply::{
    ShowDiff,
    ShowDiffWith,
    ShowDiffBetween,
+   ShowDiffAll,
+   Merge,
+   Combine,
    Skip,
    Quit,
    Help
 };
 
+use user_reply::ErrorReply;
+
 use app_result::{
    AppResult,
-   AppError
+   AppError,
+   WithPath,
+   EXIT_SUCCESS,
+   EXIT_NO_CONFLICTS,
+   EXIT_QUIT,
+   EXIT_FAILED_CONFLICTS,
+   EXIT_INVALID_USAGE
 };
 
 use args::{
+   Options,
    ResolveWuala,
    ResolveDropbox,
+   ResolveDefault,
+   ListTrash,
+   PurgeTrash,
+   PrintTrashStats,
+   RestoreTrash,
+   PrintLog,
+   Watch,
+   ListConflicts,
+   ShowConfig,
    PrintHelp,
    InvalidUsage
 };
 
 use file_system::{
-   move_to_trash,
+   FileState,
    move_file,
-   trash_dir
+   trash_dir,
+   create_temp_dir
 };
 
+use trash::{Trash, PrivateTrash};
+
+use session_log::SessionLog;
+
+use summary::Summary;
+
+use lock::Lock;
+
+use watch::Policy;
+
+use date_time::DateTime;
+
+use config::Config;
+
+use color::Colors;
+
+use tool::{ToolFiles, ToolKind};
+
+use compare::{Comparison, BinaryComparison, SqliteComparison, ArchiveComparison, Handler, Difference};
+
+use merge::{MergeConflict, Side};
+
 mod app_result;
 mod file_system;
 mod file_conflict;
@@ -49,169 +104,1217 @@ mod parser;
 mod user_reply;
 mod appdirs;
 mod args;
+mod date_time;
+mod trash;
+mod session_log;
+mod summary;
+mod lock;
+mod watch;
+mod scan_index;
+mod config;
+mod color;
+mod tool;
+mod compare;
+mod merge;
 
 fn main()
 {
    let cmd = args::get_command();
    match cmd {
-      ResolveWuala(path) => {
-         resolve_conflicts(Wuala, &path)
+      ResolveWuala(path, options) => {
+         let outcome = configure(Some(&path), options)
+            .and_then(|(config, options)| resolve_conflicts(Wuala, &path, &options, &config))
             .unwrap_or_else(|err| { exit_with_error(&err); });
+
+         process::exit(outcome.exit_code());
+      }
+
+      ResolveDropbox(path, options) => {
+         let outcome = configure(Some(&path), options)
+            .and_then(|(config, options)| resolve_conflicts(Dropbox, &path, &options, &config))
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+
+         process::exit(outcome.exit_code());
+      }
+
+      ResolveDefault(path, options) => {
+         let outcome = configure(Some(&path), options)
+            .and_then(|(config, options)| {
+               let conf_type = config.provider.ok_or(AppError::InvalidUsage(
+                  "No conflict type given and no default 'provider' configured!".to_string()))?;
+
+               resolve_conflicts(conf_type, &path, &options, &config)
+            })
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+
+         process::exit(outcome.exit_code());
       }
 
-      ResolveDropbox(path) => {
-         resolve_conflicts(Dropbox, &path)
+      ListTrash(options) => {
+         configure(None, options)
+            .and_then(|(_, options)| list_trash(&options))
             .unwrap_or_else(|err| { exit_with_error(&err); });
       }
 
+      PurgeTrash(options) => {
+         configure(None, options)
+            .and_then(|(_, options)| purge_trash(&options))
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+      }
+
+      PrintTrashStats(options) => {
+         configure(None, options)
+            .and_then(|(_, options)| print_trash_stats(&options))
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+      }
+
+      RestoreTrash(session, dir, options) => {
+         configure(None, options)
+            .and_then(|(_, options)| restore_trash(&session, &dir, &options))
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+      }
+
+      PrintLog(options) => {
+         print_log(&options)
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+      }
+
+      Watch(conf_type, path, options) => {
+         configure(Some(&path), options)
+            .and_then(|(config, options)| watch_conflicts(conf_type, &path, &options, &config))
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+      }
+
+      ListConflicts(conf_type, path, options) => {
+         let outcome = configure(Some(&path), options)
+            .and_then(|(config, options)| list_conflicts(conf_type, &path, &options, &config))
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+
+         process::exit(outcome.exit_code());
+      }
+
+      ShowConfig(dir) => {
+         let config = Config::load(dir.as_deref())
+            .unwrap_or_else(|err| { exit_with_error(&err); });
+
+         print!("{}", config);
+      }
+
       PrintHelp => args::print_help(),
 
       InvalidUsage => {
          args::print_help();
+         process::exit(EXIT_INVALID_USAGE);
       }
    }
 }
 
-fn exit_with_error(err: &AppError)
+fn exit_with_error(err: &AppError) -> !
 {
    writeln!(&mut io::stderr(), "{}", err).unwrap();
-   process::exit(1);
+   process::exit(err.exit_code());
+}
+
+/// Loads the configuration for the start directory `start_dir` and completes `options` by it.
+fn configure(start_dir: Option<&Path>, mut options: Options) -> AppResult<(Config, Options)>
+{
+   let config = Config::load(start_dir)?;
+   config.apply_to(&mut options);
+   Ok((config, options))
+}
+
+/// How a run resolving conflicts ended.
+#[derive(PartialEq, Debug)]
+enum Outcome
+{
+   /// all conflicts have been shown to the user
+   Finished,
+
+   /// the scan didn't find any conflicts
+   NoConflicts,
+
+   /// the user quit before all conflicts have been shown
+   Quit,
+
+   /// the file operations of some conflicts failed
+   Failed
+}
+
+impl Outcome
+{
+   fn exit_code(&self) -> i32
+   {
+      match *self {
+         Outcome::Finished    => EXIT_SUCCESS,
+         Outcome::NoConflicts => EXIT_NO_CONFLICTS,
+         Outcome::Quit        => EXIT_QUIT,
+         Outcome::Failed      => EXIT_FAILED_CONFLICTS
+      }
+   }
 }
 
 /// Finds file conflicts of type `conf_type` starting at the directory `start_dir`,
 /// recursively visiting every file, asking the user how each conflict should
 /// be handled and then executing the user command.
-fn resolve_conflicts(conf_type: ConflictType, start_dir: &Path) -> AppResult<()>
+///
+/// Parts of the directory hierarchy which couldn't be scanned are reported
+/// after the scan, and with `options.strict` the run fails because of them.
+///
+/// Every conflict shown, every reply of the user and every resulting file
+/// operation is recorded in the session log, unless `options.no_log` is set.
+///
+/// The directory is locked while scanning and resolving, so that no other run of confsolve
+/// resolves its conflicts at the same time, unless `options.force` is set.
+/// Conflicts queued by the watch mode are shown first.
+///
+/// At the end a summary of what happened to the conflicts is printed, and
+/// written as report into the file `options.report`, if it's set.
+fn resolve_conflicts(conf_type: ConflictType, start_dir: &Path, options: &Options, config: &Config) -> AppResult<Outcome>
 {
-   let stdin = io::stdin();
-   let mut stdout = io::stdout();
+   let mut log = open_log(options)?;
+   let _lock = Lock::acquire(start_dir, options.force)?;
+   let mut scan = scan_conflicts(conf_type, start_dir, options, config)?;
+   queued_first(conf_type, start_dir, &mut scan.conflicts)?;
+
+   let trash = open_trash(start_dir, options, config)?;
+
+   let mut summary = Summary {
+      start_dir: start_dir.display().to_string(),
+      found: scan.conflicts.len(),
+      trash: trash.to_string(),
+      ..Summary::default()
+   };
+
+   log.log("start", start_dir, &format!("{}, {} conflict(s)", conf_type, scan.conflicts.len()))?;
+   let result = resolve_all(&scan.conflicts, start_dir, &trash, &mut log, &mut summary, options, config)
+      .map(|outcome| if scan.conflicts.is_empty() { Outcome::NoConflicts } else { outcome });
+
+   println!("\n{}", summary);
+   if let Some(ref report) = options.report {
+      summary.write_report(report)?;
+      println!("Wrote report to '{}'.", report.display());
+   }
+
+   result
+}
+
+/// Prints the conflicts of type `conf_type` starting at the directory `start_dir`.
+fn list_conflicts(conf_type: ConflictType, start_dir: &Path, options: &Options, config: &Config) -> AppResult<Outcome>
+{
+   let scan = scan_conflicts(conf_type, start_dir, options, config)?;
+   let colors = Colors::new(config.color);
+   for conf in scan.conflicts.iter() {
+      println!("{}", describe_conflict(conf, &compare::classify_conflict(conf), &colors));
+   }
+
+   println!("Found {} conflict(s).", scan.conflicts.len());
+   Ok(if scan.conflicts.is_empty() { Outcome::NoConflicts } else { Outcome::Finished })
+}
+
+/// Finds the conflicts of type `conf_type` starting at the directory `start_dir`, by the
+/// scan index unless `options.no_index` is set. Parts of the directory hierarchy which
+/// couldn't be scanned are reported, and with `options.strict` the scan fails because of them.
+/// Conflicts excluded by the configuration are left out.
+fn scan_conflicts(conf_type: ConflictType, start_dir: &Path, options: &Options, config: &Config) -> AppResult<Scan>
+{
+   let mut scan = file_conflict::find(conf_type, start_dir, ! options.no_index)?;
+   scan.conflicts.retain(|conf| ! config.is_excluded(start_dir, &conf.original_path));
+
+   let colors = Colors::new(config.color);
+   for warning in scan.warnings.iter() {
+      writeln!(&mut io::stderr(), "{}", colors.warning(format!("Warning: {}", warning)))?;
+   }
+
+   if options.strict && ! scan.warnings.is_empty() {
+      return Err(AppError::IncompleteScan { dir: start_dir.to_path_buf(), num_warnings: scan.warnings.len() });
+   }
+
+   Ok(scan)
+}
+
+/// Opens the session log, or a disabled one with `options.no_log`.
+fn open_log(options: &Options) -> AppResult<SessionLog>
+{
+   if options.no_log {
+      return Ok(SessionLog::disabled());
+   }
+
+   SessionLog::open(&session_log::log_dir()?)
+}
+
+/// Returns the trash chosen by `options` for the conflicts of `start_dir`.
+/// The private trash is purged by the retention setting of `config` before.
+fn open_trash(start_dir: &Path, options: &Options, config: &Config) -> AppResult<Trash>
+{
+   if options.freedesktop_trash {
+      return Ok(Trash::Freedesktop);
+   }
+
+   let trash_dir = trash_dir(options.trash_dir.as_deref())?;
+   if let Some(ref retention) = config.retention {
+      purge_trash_by_retention(&trash_dir, retention)?;
+   }
+
+   Ok(Trash::Private(PrivateTrash::new(trash_dir, start_dir, options.dedup_trash)))
+}
 
-   let confs = file_conflict::find(conf_type, start_dir)?;
-   for conf in confs.iter() {
+/// Moves the conflicts of `confs` queued by the watch mode to the front,
+/// in the order they've been queued, and removes them from the queue.
+fn queued_first(conf_type: ConflictType, start_dir: &Path, confs: &mut [Conflict]) -> AppResult<()>
+{
+   let canonical = |path: &Path| -> PathBuf {
+      match (path.parent().and_then(|p| fs::canonicalize(p).ok()), path.file_name()) {
+         (Some(dir), Some(name)) => dir.join(name),
+         _                       => path.to_path_buf()
+      }
+   };
+
+   let start_dir = fs::canonicalize(start_dir).with_path(start_dir)?;
+   let queued = watch::take_queued(conf_type, &start_dir)?;
+   if queued.is_empty() {
+      return Ok(());
+   }
+
+   confs.sort_by_key(|conf| {
+      let orig = canonical(&conf.original_path);
+      queued.iter().position(|q| *q == orig).unwrap_or(queued.len())
+   });
+
+   // queued conflicts might have been resolved in the meantime
+   let num_queued = confs.iter().filter(|conf| queued.contains(&canonical(&conf.original_path))).count();
+   if num_queued > 0 {
+      println!("{} conflict(s) have been queued by 'confsolve watch' and are shown first.", num_queued);
+   }
+
+   Ok(())
+}
+
+/// Watches the directory hierarchy `start_dir` for new conflicts of type `conf_type`
+/// and handles them by `options.policy` until the application is interrupted.
+/// Conflicts excluded by the configuration are ignored.
+#[cfg(target_os = "linux")]
+fn watch_conflicts(conf_type: ConflictType, start_dir: &Path, options: &Options, config: &Config) -> AppResult<()>
+{
+   let start_dir = fs::canonicalize(start_dir).with_path(start_dir)?;
+   let policy = options.policy.unwrap_or(Policy::Notify);
+   let mut log = open_log(options)?;
+   let trash = open_trash(&start_dir, options, config)?;
+   let mut watcher = watch::Watcher::new(&start_dir)?;
+   let colors = Colors::new(config.color);
+
+   println!("Watching '{}' for {} conflicts with the policy '{}', press Ctrl-C to stop.",
+            start_dir.display(), conf_type, policy);
+
+   log.log("watch", &start_dir, &format!("{}, policy {}", conf_type, policy))?;
+   loop {
+      // the conflicting files of a conflict - or all files after an overflow
+      // of the event queue - might be in the same batch of events
+      let mut handled = Vec::new();
+      for file in watcher.wait()? {
+         if ! file.is_file() || trash.contains(&file) || config.is_excluded(&start_dir, &file) {
+            continue;
+         }
+
+         let result = file_conflict::conflict_of(conf_type, &file)
+            .and_then(|conf| match conf {
+               Some(ref conf) if handled.contains(&conf.original_path) => Ok(()),
+               Some(conf) => {
+                  handled.push(conf.original_path.clone());
+                  handle_new_conflict(&conf, conf_type, &start_dir, options, &trash, &mut log, &colors)
+               }
+
+               None => Ok(())
+            });
+
+         // a failed conflict shouldn't stop watching
+         if let Err(err) = result {
+            writeln!(&mut io::stderr(), "Error: {}", err)?;
+            log.log("error", &file, &err.to_string())?;
+         }
+      }
+   }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_conflicts(_conf_type: ConflictType, _start_dir: &Path, _options: &Options, _config: &Config) -> AppResult<()>
+{
+   Err(AppError::from_string("The watch mode is only supported on Linux!".to_string()))
+}
+
+/// Handles the conflict `conf` found by the watch mode by the policy of `options`.
+/// With `options.resolve_trivial` the original file of a conflict, which differs
+/// only in its formatting, is kept.
+fn handle_new_conflict(conf: &Conflict,
+                       conf_type: ConflictType,
+                       start_dir: &Path,
+                       options: &Options,
+                       trash: &Trash,
+                       log: &mut SessionLog,
+                       colors: &Colors) -> AppResult<()>
+{
+   let differences = compare::classify_conflict(conf);
+   println!("\n{}: New conflict\n{}", DateTime::now(), describe_conflict(conf, &differences, colors));
+   log.log("detected", &conf.original_path, &conf.conflicting_files.iter()
+      .map(|f| f.details.clone())
+      .collect::<Vec<String>>()
+      .join("; "))?;
+
+   let policy = if options.resolve_trivial && is_trivial(&differences) {
+      Policy::KeepOriginal
+   } else {
+      options.policy.unwrap_or(Policy::Notify)
+   };
+
+   // keeping the original file isn't possible without it
+   let policy = if policy == Policy::KeepOriginal && ! conf.original_path.is_file() { Policy::Queue } else { policy };
+   match policy {
+      Policy::Notify => {
+         // the desktop notification is optional
+         let _ = Command::new("notify-send")
+            .arg("confsolve")
+            .arg(format!("New conflict: {}", conf.original_path.display()))
+            .output();
+      }
+
+      Policy::Queue => {
+         watch::enqueue(conf_type, &conf.original_path)?;
+         println!("Queued the conflict for the next interactive session.");
+      }
+
+      Policy::KeepOriginal | Policy::TakeNewest => {
+         let _lock = match Lock::acquire(start_dir, false) {
+            Ok(lock) => lock,
+            Err(AppError::Locked { .. }) => {
+               watch::enqueue(conf_type, &conf.original_path)?;
+               println!("The directory is locked by another run of confsolve, queued the conflict.");
+               return Ok(());
+            }
+
+            Err(err) => return Err(err)
+         };
+
+         let mut summary = Summary::default();
+         match newest_file(conf) {
+            Some(num) if policy == Policy::TakeNewest => {
+               take_file(conf, num, trash, log, &mut summary)?;
+               println!("Took the newest file '{}'.", conf.conflicting_files[num - 1].path.display());
+            }
+
+            _ => {
+               trash_all(conf, trash, log, &mut summary)?;
+               println!("Kept the original file, moved the conflicting files into {}.", trash);
+            }
+         }
+
+         log.log("auto", &conf.original_path, &policy.to_string())?;
+      }
+   }
+
+   Ok(())
+}
+
+/// Returns the number of the most recently modified conflicting file of `conf`,
+/// or none if the original file is at least as recent as all conflicting files.
+fn newest_file(conf: &Conflict) -> Option<usize>
+{
+   let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+   let mut newest = (None, modified(&conf.original_path));
+   for (i, conf_file) in conf.conflicting_files.iter().enumerate() {
+      let time = modified(&conf_file.path);
+      if time.is_some() && time > newest.1 {
+         newest = (Some(i + 1), time);
+      }
+   }
+
+   newest.0
+}
+
+/// How one conflict has been handled by the user.
+enum Resolution
+{
+   Taken,
+   Trashed,
+   Merged,
+   Skipped,
+   Failed,
+   Quit,
+   FailedAndQuit
+}
+
+/// Asks the user for every conflict of `confs` how it should be handled
+/// and counts what happened in `summary`. With `options.resolve_trivial` the
+/// original files of conflicts, which differ only in their formatting, are kept
+/// without asking.
+fn resolve_all(confs: &[Conflict],
+               start_dir: &Path,
+               trash: &Trash,
+               log: &mut SessionLog,
+               summary: &mut Summary,
+               options: &Options,
+               config: &Config) -> AppResult<Outcome>
+{
+   let colors = Colors::new(config.color);
+   for (i, conf) in confs.iter().enumerate() {
       if ! conf.original_path.is_file() {
          println!("\nFound conflicts for the file '{}', but the file itself is missing! Skipping it.",
                   conf.original_path.display());
+         log.log("missing", &conf.original_path, "")?;
+         summary.orphaned += 1;
          continue;
       }
 
+      let differences = compare::classify_conflict(conf);
+      let resolution = if options.resolve_trivial && is_trivial(&differences) {
+         println!("\n{}", describe_conflict(conf, &differences, &colors));
+         println!("Only the formatting differs, keeping the current file.");
+         log.log("auto", &conf.original_path, "trivial")?;
+         execute(Resolution::Trashed, conf, log, summary, options.keep_going,
+                 |log, summary| trash_all(conf, trash, log, summary))
+      } else {
+         resolve_conflict(conf, differences, trash, log, summary, options.keep_going, config)
+      };
+
+      match resolution {
+         Ok(Resolution::Taken)   => summary.taken += 1,
+         Ok(Resolution::Trashed) => summary.trashed += 1,
+         Ok(Resolution::Merged)  => summary.merged += 1,
+         Ok(Resolution::Skipped) => summary.skipped += 1,
+         Ok(Resolution::Failed)  => summary.errored += 1,
+
+         Ok(Resolution::Quit) => {
+            summary.left = confs.len() - i;
+            log.log("end", start_dir, "quit")?;
+            return Ok(Outcome::Quit);
+         }
+
+         Ok(Resolution::FailedAndQuit) => {
+            summary.errored += 1;
+            summary.left = confs.len() - i - 1;
+            log.log("end", start_dir, "quit")?;
+            return Ok(Outcome::Quit);
+         }
+
+         Err(err) => {
+            summary.errored += 1;
+            summary.left = confs.len() - i - 1;
+            let _ = log.log("error", &conf.original_path, &err.to_string());
+            return Err(err);
+         }
+      }
+   }
+
+   log.log("end", start_dir, "finished")?;
+   if summary.errored > 0 {
+      return Ok(Outcome::Failed);
+   }
+
+   Ok(Outcome::Finished)
+}
+
+/// Shows the conflict `conf` to the user and executes the replies of the
+/// user until the conflict is resolved, skipped or the user quits.
+///
+/// If files of the conflict have been changed on disk since the conflict has
+/// been shown - e.g. by a still running synchronization - the conflict is
+/// shown again instead of executing the reply.
+///
+/// The keys of the replies and the diff and merge commands are taken from `config`.
+fn resolve_conflict(conf: &Conflict,
+                    differences: Vec<Difference>,
+                    trash: &Trash,
+                    log: &mut SessionLog,
+                    summary: &mut Summary,
+                    keep_going: bool,
+                    config: &Config) -> AppResult<Resolution>
+{
+   let stdin = io::stdin();
+   let mut stdout = io::stdout();
+   let colors = Colors::new(config.color);
+   let prompt = user_reply::prompt(&config.keys);
+
+   // the conflict without the conflicting files already combined into the original file
+   let mut remaining;
+   let mut conf = conf;
+   let mut differences = Some(differences);
+
+   'show_conflict: loop {
       let num_conf_files = conf.conflicting_files.len();
-      println!("\n{}", conf);
+      let details = conf.conflicting_files.iter()
+         .enumerate()
+         .map(|(i, f)| format!("({}) {}", i + 1, f.details))
+         .collect::<Vec<String>>();
+
+      // the files might have changed since the conflict was classified
+      let differences = differences.take().unwrap_or_else(|| compare::classify_conflict(conf));
+      println!("\n{}", describe_conflict(conf, &differences, &colors));
+      log.log("conflict", &conf.original_path, &details.join("; "))?;
+
+      // the state of the files as shown to the user
+      let states = conf.file_states();
 
       loop {
-         print!("{}", "(T)ake File (NUM) | (M)ove to Trash | Show (D)iff (NUM [NUM]) | (S)kip | (Q)uit | (H)elp: ");
+         print!("{}", prompt);
          let _ = stdout.flush();
 
          let mut line = String::new();
-         stdin.read_line(&mut line)?;
+         if stdin.read_line(&mut line)? == 0 {
+            // no more input, e.g. at the end of a piped file
+            println!();
+            return Ok(Resolution::Quit);
+         }
 
-         match user_reply::parse(&line, num_conf_files) {
+         match user_reply::parse(&line, num_conf_files, &config.keys) {
             Some(reply) => {
+               log.log("reply", &conf.original_path, &reply.to_string())?;
                match reply {
                   TakeFile(num) => {
-                     let ref take_file = conf.conflicting_files[num - 1].path;
-                     for conf_file in conf.conflicting_files.iter() {
-                        if conf_file.path != *take_file {
-                           move_to_trash(&conf_file.path)?;
-                        }
+                     if files_changed(&states, log)? {
+                        continue 'show_conflict;
                      }
 
-                     move_to_trash(&conf.original_path)?;
-                     move_file(take_file, &conf.original_path)?;
-
-                     break;
+                     return execute(Resolution::Taken, conf, log, summary, keep_going,
+                                    |log, summary| take_file(conf, num, trash, log, summary));
                   }
 
                   MoveToTrash => {
-                     for conf_file in conf.conflicting_files.iter() {
-                        move_to_trash(&conf_file.path)?;
+                     if files_changed(&states, log)? {
+                        continue 'show_conflict;
                      }
 
-                     break;
+                     return execute(Resolution::Trashed, conf, log, summary, keep_going,
+                                    |log, summary| trash_all(conf, trash, log, summary));
                   }
 
-                  ShowDiff => {
-                     show_diff(&conf.original_path, &conf.conflicting_files[0].path)?;
-                  }
+                  ShowDiff                    => show_diff(conf, 0, 1, config, &colors),
+                  ShowDiffWith(num)           => show_diff(conf, 0, num, config, &colors),
+                  ShowDiffBetween(num1, num2) => show_diff(conf, num1, num2, config, &colors),
+                  ShowDiffAll                 => show_diff_all(conf, config, &colors),
+
+                  Merge(num) => {
+                     match config.merge {
+                        Some(ref merge) => {
+                           if call_tool(ToolKind::Merge, merge, &tool_files(conf, 0, num), &colors) {
+                              // the merge changed the files, so they're shown with their new state
+                              continue 'show_conflict;
+                           }
+                        }
 
-                  ShowDiffWith(num) => {
-                     show_diff(&conf.original_path, &conf.conflicting_files[num - 1].path)?;
+                        None => println!("\nNo merge tool configured, set 'merge' in '{}'!\n",
+                                         config::user_config_file().unwrap_or_default().display())
+                     }
                   }
 
-                  ShowDiffBetween(num1, num2) => {
-                     show_diff(&conf.conflicting_files[num1 - 1].path,
-                               &conf.conflicting_files[num2 - 1].path)?;
+                  Combine(num) => {
+                     if files_changed(&states, log)? {
+                        continue 'show_conflict;
+                     }
+
+                     match combine(conf, num) {
+                        Ok(Some(text)) => {
+                           let resolution = execute(Resolution::Merged, conf, log, summary, keep_going,
+                                                    |log, summary| write_merged(conf, num, &text, trash, log, summary))?;
+
+                           match resolution {
+                              Resolution::Merged if num_conf_files > 1 => (),
+                              _                                        => return Ok(resolution)
+                           }
+
+                           // the other conflicting files might still have changes of their own
+                           let mut conflicting_files = conf.conflicting_files.clone();
+                           conflicting_files.remove(num - 1);
+                           remaining = Conflict { original_path: conf.original_path.clone(), conflicting_files };
+                           conf = &remaining;
+                           println!("\nThe conflicting file ({}) has been combined, {} conflicting file(s) are left.",
+                                    num, conf.conflicting_files.len());
+
+                           continue 'show_conflict;
+                        }
+
+                        Ok(None) => (),
+                        Err(err) => { succeeded(Err(err), &colors); }
+                     }
                   }
 
-                  Skip => { break; }
-                  Quit => { return Ok(()); }
-                  Help => print_runtime_help()?
+                  Skip => return Ok(Resolution::Skipped),
+                  Quit => return Ok(Resolution::Quit),
+                  Help => print_runtime_help(trash, config)
                }
             }
 
             None => {
                // remove newline at end of line
                line.pop();
-               println!("\nInvalid user input: '{}' !\n", line);
+               println!("\n{}\n", colors.error(format!("Invalid user input: '{}' !", line)));
             }
          }
       }
    }
+}
+
+/// Formats `conf` for the user, with the conflicting files which differ only in their
+/// formatting. The conflicting files differ from the original file by `differences`.
+fn describe_conflict(conf: &Conflict, differences: &[Difference], colors: &Colors) -> String
+{
+   let mut text = colors.conflict(conf);
+   for (i, difference) in differences.iter().enumerate() {
+      if difference.is_trivial() {
+         text.push_str(&format!("   ({}) {}\n", i + 1, difference));
+      }
+   }
+
+   text
+}
+
+/// If all conflicting files differ only in their formatting from the original file.
+fn is_trivial(differences: &[Difference]) -> bool
+{
+   differences.iter().all(|difference| difference.is_trivial())
+}
+
+/// Executes the file operations `action` of the conflict `conf`. If they fail, the error
+/// is reported and the user is asked whether they should be retried, the conflict skipped
+/// or the application quit. With `keep_going` the conflict is skipped without asking.
+/// Returns `resolution` if the file operations succeeded.
+fn execute<F>(resolution: Resolution,
+              conf: &Conflict,
+              log: &mut SessionLog,
+              summary: &mut Summary,
+              keep_going: bool,
+              mut action: F) -> AppResult<Resolution>
+   where F: FnMut(&mut SessionLog, &mut Summary) -> AppResult<()>
+{
+   loop {
+      let err = match action(log, summary) {
+         Ok(())   => return Ok(resolution),
+         Err(err) => err
+      };
+
+      writeln!(&mut io::stderr(), "\nError: {}", err)?;
+      log.log("error", &conf.original_path, &err.to_string())?;
+      if keep_going {
+         println!("Skipping the conflict because of '--keep-going'.");
+         return Ok(Resolution::Failed);
+      }
+
+      let reply = ask_after_error()?;
+      log.log("reply", &conf.original_path, &reply.to_string())?;
+      match reply {
+         ErrorReply::Retry          => continue,
+         ErrorReply::SkipConflict   => return Ok(Resolution::Failed),
+         ErrorReply::QuitAfterError => return Ok(Resolution::FailedAndQuit)
+      }
+   }
+}
+
+/// Asks the user how to continue after a failed file operation.
+fn ask_after_error() -> AppResult<ErrorReply>
+{
+   let stdin = io::stdin();
+   let mut stdout = io::stdout();
+   loop {
+      print!("(R)etry | (S)kip | (Q)uit: ");
+      let _ = stdout.flush();
+
+      let mut line = String::new();
+      if stdin.read_line(&mut line)? == 0 {
+         println!();
+         return Ok(ErrorReply::QuitAfterError);
+      }
+
+      match user_reply::parse_error_reply(&line) {
+         Some(reply) => return Ok(reply),
+         None        => println!("\nInvalid user input: '{}' !\n", line.trim_end())
+      }
+   }
+}
+
+/// Checks if files of a conflict have been changed since their `states` have
+/// been recorded, and if so, warns the user and records the changes in `log`.
+fn files_changed(states: &[FileState], log: &mut SessionLog) -> AppResult<bool>
+{
+   let changed = states.iter().filter(|s| s.has_changed()).collect::<Vec<&FileState>>();
+   if changed.is_empty() {
+      return Ok(false);
+   }
+
+   println!("\nWarning: Files of the conflict have been changed on disk since it has been shown:");
+   for state in changed {
+      let what = if state.path.is_file() { "changed" } else { "removed" };
+      println!("   {} ({})", state.path.display(), what);
+      log.log("changed", &state.path, what)?;
+   }
+
+   println!("The reply isn't executed, the conflict is shown again.");
+   Ok(true)
+}
+
+/// Uses the conflicting file `num` of `conf` as the new version of the original file,
+/// after putting the original file and the other conflicting files into `trash`.
+/// Files which are already gone - e.g. trashed by a failed try before - are skipped.
+fn take_file(conf: &Conflict, num: usize, trash: &Trash, log: &mut SessionLog, summary: &mut Summary) -> AppResult<()>
+{
+   let take_file = &conf.conflicting_files[num - 1].path;
+   for conf_file in conf.conflicting_files.iter() {
+      if conf_file.path != *take_file && conf_file.path.is_file() {
+         summary.trashed_bytes += put_into_trash(trash, &conf_file.path, log)?;
+      }
+   }
+
+   if conf.original_path.is_file() {
+      summary.trashed_bytes += put_into_trash(trash, &conf.original_path, log)?;
+   }
+
+   move_file(take_file, &conf.original_path)?;
+   log.log("move", take_file, &format!("-> {}", conf.original_path.display()))?;
+   Ok(())
+}
+
+/// Puts the original file and the conflicting file `num` of `conf` into `trash`, skipping the
+/// ones already gone, and writes `text` - the merge of both files - as the original file.
+fn write_merged(conf: &Conflict, num: usize, text: &str, trash: &Trash, log: &mut SessionLog, summary: &mut Summary) -> AppResult<()>
+{
+   for file in [&conf.conflicting_files[num - 1].path, &conf.original_path].iter() {
+      if file.is_file() {
+         summary.trashed_bytes += put_into_trash(trash, file, log)?;
+      }
+   }
+
+   fs::write(&conf.original_path, text).with_path(&conf.original_path)?;
+   log.log("merge", &conf.original_path, &format!("{} bytes", text.len()))?;
+   Ok(())
+}
+
+/// Puts all conflicting files of `conf` into `trash`, skipping the ones already gone.
+fn trash_all(conf: &Conflict, trash: &Trash, log: &mut SessionLog, summary: &mut Summary) -> AppResult<()>
+{
+   for conf_file in conf.conflicting_files.iter() {
+      if conf_file.path.is_file() {
+         summary.trashed_bytes += put_into_trash(trash, &conf_file.path, log)?;
+      }
+   }
 
    Ok(())
 }
 
-/// Calls the diff command specified by the environment variable `CONFSOLVE_DIFF`
-/// or - if not defined - `gvimdiff -f` with the files `file1` and `file2`.
-fn show_diff(file1: &Path, file2: &Path) -> AppResult<()>
+/// Moves `file` into `trash`, records it in `log` and returns the size of `file`.
+fn put_into_trash(trash: &Trash, file: &Path, log: &mut SessionLog) -> AppResult<u64>
+{
+   let size = fs::metadata(file).with_path(file)?.len();
+   let trash_file = trash.put(file)?;
+   log.log("trash", file, &format!("-> {}", trash_file.display()))?;
+   Ok(size)
+}
+
+/// Prints the events of the session log matching the path and date of `options`.
+fn print_log(options: &Options) -> AppResult<()>
 {
-   let diff_cmd_and_args = env::var("CONFSOLVE_DIFF").unwrap_or("gvimdiff -f".to_string());
-   let diff_cmd_and_args = diff_cmd_and_args.split(' ').collect::<Vec<&str>>();
+   let log_dir = session_log::log_dir()?;
+   let entries = session_log::query(&log_dir, options.log_path.as_deref(), options.log_date.as_deref())?;
+   for entry in entries.iter() {
+      println!("{}  {}  {:<8}  {}  {}", entry.time, entry.who, entry.event, entry.path, entry.details);
+   }
 
-   let diff_cmd = diff_cmd_and_args[0];
+   Ok(())
+}
 
-   let args = diff_cmd_and_args.iter().skip(1);
-   let mut cmd = Command::new(diff_cmd);
-   for arg in args {
-      cmd.arg(arg);
+/// Prints the sessions of the private trash directory with their trashed files.
+fn list_trash(options: &Options) -> AppResult<()>
+{
+   let trash_dir = trash_dir(options.trash_dir.as_deref())?;
+   let sessions = trash::sessions(&trash_dir)?;
+   if sessions.is_empty() {
+      println!("The trash directory '{}' is empty.", trash_dir.display());
+      return Ok(());
    }
 
-   cmd.arg(file1);
-   cmd.arg(file2);
+   println!("Trash directory: {}", trash_dir.display());
+   for session in sessions.iter() {
+      if session.name.is_empty() {
+         println!("\nWithout session:");
+      }
+      else {
+         println!("\nSession {}:", session.name);
+      }
+
+      for file in session.files.iter() {
+         println!("   {}", file.display());
+      }
+   }
 
-   cmd.output()?;
    Ok(())
 }
 
-fn print_runtime_help() -> AppResult<()>
+/// Purges the private trash directory by the limits of `options`.
+fn purge_trash(options: &Options) -> AppResult<()>
+{
+   let trash_dir = trash_dir(options.trash_dir.as_deref())?;
+   let purged = trash::purge(&trash_dir, options.older_than, options.max_size)?;
+   println!("Purged {} session(s) with {} file(s) and {} from the trash directory '{}'.",
+            purged.sessions, purged.files, trash::format_size(purged.bytes), trash_dir.display());
+
+   Ok(())
+}
+
+/// Purges the sessions of the private trash directory `trash_dir`, which are older than
+/// the age `retention`, e.g. `30d`.
+fn purge_trash_by_retention(trash_dir: &Path, retention: &str) -> AppResult<()>
+{
+   let older_than = trash::parse_age(retention)
+      .ok_or(AppError::InvalidUsage(format!("Invalid trash retention '{}'!", retention)))?;
+
+   let purged = trash::purge(trash_dir, Some(older_than), None)?;
+   if purged.files > 0 {
+      println!("Purged {} session(s) with {} file(s) and {} older than {} from the trash directory '{}'.",
+               purged.sessions, purged.files, trash::format_size(purged.bytes), retention, trash_dir.display());
+   }
+
+   Ok(())
+}
+
+/// Copies the files of the trash session `session` into the directory `to_dir`.
+fn restore_trash(session: &str, to_dir: &Path, options: &Options) -> AppResult<()>
+{
+   let trash_dir = trash_dir(options.trash_dir.as_deref())?;
+   let num_restored = trash::restore(&trash_dir, session, to_dir)?;
+   println!("Restored {} file(s) of session {} into '{}'.", num_restored, session, to_dir.display());
+   Ok(())
+}
+
+/// Prints the statistics of the private trash directory.
+fn print_trash_stats(options: &Options) -> AppResult<()>
+{
+   let trash_dir = trash_dir(options.trash_dir.as_deref())?;
+   let stats = trash::stats(&trash_dir)?;
+   println!("Trash directory: {}", trash_dir.display());
+   println!("Sessions       : {}", stats.sessions);
+   println!("Files          : {}", stats.files);
+   println!("Size           : {}", trash::format_size(stats.bytes));
+   println!("Size on disk   : {}", trash::format_size(stats.stored_bytes));
+   if let (Some(oldest), Some(newest)) = (stats.oldest, stats.newest) {
+      println!("Oldest session : {}", oldest);
+      println!("Newest session : {}", newest);
+   }
+
+   Ok(())
+}
+
+/// Calls the diff or merge command line `tool` - e.g. `gvimdiff -f` - with `files`.
+/// A failure of the tool is reported, but doesn't end the session. Returns if the tool succeeded.
+fn call_tool(kind: ToolKind, tool: &str, files: &ToolFiles, colors: &Colors) -> bool
+{
+   succeeded(tool::command(tool, files).and_then(|cmd| tool::run(kind, cmd)), colors)
+}
+
+/// Shows the difference between the files `left` and `right` of `conf` - see `tool_file` -
+/// by the diff tool, or for binary files the comparison of their properties.
+fn show_diff(conf: &Conflict, left: usize, right: usize, config: &Config, colors: &Colors)
+{
+   let files = [tool_file(conf, left), tool_file(conf, right)];
+   let result = compare::handler_for(&[files[0].1, files[1].1], &config.handlers).and_then(|handler| {
+      match handler {
+         Handler::Text => {
+            tool::command(&config.diff, &tool_files(conf, left, right)).and_then(|cmd| tool::run(ToolKind::Diff, cmd))
+         }
+
+         Handler::Sqlite => {
+            println!("\n{}", SqliteComparison::new(&files)?);
+            if config.sqlite_dump { diff_sqlite_dumps(conf, left, right, config) } else { Ok(()) }
+         }
+
+         Handler::Archive => {
+            let comparison = ArchiveComparison::new(&files[0], &files[1])?;
+            println!("\n{}", comparison);
+            diff_archive_entries(conf, left, right, &comparison, config)
+         }
+
+         _ => BinaryComparison::new(handler, &files).map(|comparison| println!("\n{}", comparison))
+      }
+   });
+
+   succeeded(result, colors);
+}
+
+/// Calls the diff tool with the SQL dumps of the SQLite databases `left` and `right` of `conf`.
+fn diff_sqlite_dumps(conf: &Conflict, left: usize, right: usize, config: &Config) -> AppResult<()>
+{
+   with_temp_dir(|dir| {
+      let files = tool_files(conf, left, right);
+      let name = conf.original_path.file_name().unwrap_or_default().to_string_lossy();
+      let dump_file = |num: usize| dir.join(format!("{}.{}.sql", name, if num == 0 { "current".to_string() } else { num.to_string() }));
+      let (left_dump, right_dump) = (dump_file(left), dump_file(right));
+
+      compare::dump_sqlite(files.left, &left_dump)?;
+      compare::dump_sqlite(files.right, &right_dump)?;
+      let dumps = ToolFiles { left: &left_dump, right: &right_dump, ..files };
+      tool::command(&config.diff, &dumps).and_then(|cmd| tool::run(ToolKind::Diff, cmd))
+   })
+}
+
+/// Calls the diff tool for a changed text entry of `comparison`, the comparison of the
+/// archives `left` and `right` of `conf`. If there are several ones, the user is asked
+/// which one to diff - one after another - so that the diff tool isn't started for all of them.
+fn diff_archive_entries(conf: &Conflict,
+                        left: usize,
+                        right: usize,
+                        comparison: &ArchiveComparison,
+                        config: &Config) -> AppResult<()>
+{
+   let num_changes = comparison.text_changes.len();
+   let stdin = io::stdin();
+   let mut stdout = io::stdout();
+   let mut choose = || -> AppResult<Option<usize>> {
+      println!("\nChanged text entries:");
+      for (i, change) in comparison.text_changes.iter().enumerate() {
+         println!("   ({}) {}", i + 1, change.name);
+      }
+
+      loop {
+         print!("Diff (NUM) | (D)one: ");
+         let _ = stdout.flush();
+
+         let mut line = String::new();
+         if stdin.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(None);
+         }
+
+         match line.trim().to_lowercase().as_str() {
+            "d" | "" => return Ok(None),
+            input    => match input.parse::<usize>() {
+               Ok(num) if num >= 1 && num <= num_changes => return Ok(Some(num - 1)),
+               _                                         => println!("Invalid user input: '{}' !", line.trim_end())
+            }
+         }
+      }
+   };
+
+   with_temp_dir(|dir| {
+      let files = tool_files(conf, left, right);
+      loop {
+         let i = match num_changes {
+            0 => return Ok(()),
+            1 => 0,
+            _ => match choose()? {
+               Some(i) => i,
+               None    => return Ok(())
+            }
+         };
+
+         let change = &comparison.text_changes[i];
+
+         // the entry is written with its file name only, so its path can't escape the directory
+         let name = change.name.rsplit('/').next().unwrap_or_default();
+         let (left_dir, right_dir) = (dir.join(format!("{}-a", i)), dir.join(format!("{}-b", i)));
+         let (left_file, right_file) = (left_dir.join(name), right_dir.join(name));
+         for (entry_dir, entry_file, text) in [(&left_dir, &left_file, &change.left), (&right_dir, &right_file, &change.right)] {
+            fs::create_dir_all(entry_dir).with_path(entry_dir)?;
+            fs::write(entry_file, text).with_path(entry_file)?;
+         }
+
+         let entries = ToolFiles {
+            left       : &left_file,
+            right      : &right_file,
+            base       : files.base,
+            left_label : format!("{}: {}", files.left_label, change.name),
+            right_label: format!("{}: {}", files.right_label, change.name)
+         };
+
+         println!("Diff of '{}'", change.name);
+         tool::command(&config.diff, &entries).and_then(|cmd| tool::run(ToolKind::Diff, cmd))?;
+         if num_changes == 1 {
+            return Ok(());
+         }
+      }
+   })
+}
+
+/// Calls `action` with a new temporary directory, which is removed afterwards.
+fn with_temp_dir<F>(action: F) -> AppResult<()>
+   where F: FnOnce(&Path) -> AppResult<()>
+{
+   let dir = create_temp_dir()?;
+   let result = action(&dir);
+   let _ = fs::remove_dir_all(&dir);
+   result
+}
+
+/// Prints which files of `conf` have the same contents and calls the command line
+/// `config.diff_all` - if it's set - with all files of `conf` at once.
+fn show_diff_all(conf: &Conflict, config: &Config, colors: &Colors)
+{
+   let files = (0..conf.conflicting_files.len() + 1)
+      .map(|num| tool_file(conf, num))
+      .collect::<Vec<(String, &Path)>>();
+
+   if ! succeeded(Comparison::new(&files).map(|comparison| println!("\n{}", comparison)), colors) {
+      return;
+   }
+
+   let paths = files.iter().map(|&(_, path)| path).collect::<Vec<&Path>>();
+   if compare::handler_for(&paths, &config.handlers).ok() == Some(Handler::Sqlite) {
+      succeeded(SqliteComparison::new(&files).map(|comparison| println!("{}", comparison)), colors);
+   }
+
+   if let Some(ref diff_all) = config.diff_all {
+      succeeded(tool::command_all(diff_all, &paths).and_then(|cmd| tool::run(ToolKind::DiffAll, cmd)), colors);
+   }
+}
+
+/// Reports the error of `result`, without ending the session. Returns if there's none.
+fn succeeded(result: AppResult<()>, colors: &Colors) -> bool
+{
+   match result {
+      Ok(())   => true,
+      Err(err) => {
+         let _ = writeln!(&mut io::stderr(), "\n{}\n", colors.error(format!("Error: {}", err)));
+         false
+      }
+   }
+}
+
+/// Merges the original file of `conf` with the conflicting file `num` by the built-in merge
+/// for their type and asks the user which version to use for the parts changed in both files.
+/// Returns the merged contents, none if there's no built-in merge or the user aborted it.
+fn combine(conf: &Conflict, num: usize) -> AppResult<Option<String>>
 {
-   let trash_dir = trash_dir()?;
-   let dir_str   = trash_dir.display();
+   let merger = match merge::merger_for(&conf.original_path) {
+      Some(merger) => merger,
+      None         => {
+         println!("\nNo built-in merge for '{}', only JSON, YAML, TOML, INI, calendar (.ics), contact (.vcf) and Markdown files can be combined!\n",
+                  conf.original_path.display());
+
+         return Ok(None);
+      }
+   };
+
+   let conf_file = &conf.conflicting_files[num - 1].path;
+   let current = fs::read_to_string(&conf.original_path).with_path(&conf.original_path)?;
+   let conflicting = fs::read_to_string(conf_file).with_path(conf_file)?;
+
+   let stdin = io::stdin();
+   let mut stdout = io::stdout();
+   let indent = |text: &str| text.replace('\n', "\n                    ");
+   let mut choose = |conflict: &MergeConflict| -> AppResult<Option<Side>> {
+      println!("\nChanged in both files: {}\n   (1) current    : {}\n   (2) conflicting: {}",
+               conflict.what, indent(&conflict.current), indent(&conflict.conflicting));
+
+      loop {
+         print!("Use (1) current | (2) conflicting | (A)bort: ");
+         let _ = stdout.flush();
+
+         let mut line = String::new();
+         if stdin.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(None);
+         }
+
+         match line.trim().to_lowercase().as_str() {
+            "1" => return Ok(Some(Side::Current)),
+            "2" => return Ok(Some(Side::Conflicting)),
+            "a" => return Ok(None),
+            _   => println!("Invalid user input: '{}' !", line.trim_end())
+         }
+      }
+   };
+
+   match merger.merge(&current, &conflicting, &mut choose)? {
+      Some(merged) => {
+         println!("\nCombined the {}: {} part(s) added from the conflicting file, {} changed in both.",
+                  merger, merged.added, merged.conflicts);
+
+         Ok(Some(merged.text))
+      }
+
+      None => {
+         println!("\nThe combining has been aborted.\n");
+         Ok(None)
+      }
+   }
+}
+
+/// The files `left` and `right` of `conf` for a diff or merge command, see `tool_file`.
+fn tool_files(conf: &Conflict, left: usize, right: usize) -> ToolFiles<'_>
+{
+   let (left_label, left) = tool_file(conf, left);
+   let (right_label, right) = tool_file(conf, right);
+   ToolFiles { left, right, base: &conf.original_path, left_label, right_label }
+}
+
+/// The description and the path of the file `num` of `conf`, the number 0
+/// is the original file, the others are the conflicting files.
+fn tool_file(conf: &Conflict, num: usize) -> (String, &Path)
+{
+   let name = conf.original_path.file_name().unwrap_or_default().to_string_lossy();
+   match num {
+      0   => (format!("{} (current)", name), &conf.original_path),
+      num => {
+         let conf_file = &conf.conflicting_files[num - 1];
+         (format!("{} ({}) {}", name, num, conf_file.details), &conf_file.path)
+      }
+   }
+}
+
+fn print_runtime_help(trash: &Trash, config: &Config)
+{
+   let keys = &config.keys;
+   let label = |name: &str, key: char, num: &str| format!("{:<17}", user_reply::label(name, key) + num);
+   let merge = match config.merge {
+      Some(ref merge) => format!("The merge tool is '{}'.", merge),
+      None            => "No merge tool is configured yet.".to_string()
+   };
 
    println!("
 Runtime Options:
-   (T)ake File (NUM) => By pressing 't' and a number (e.g 't1'), the conflicting file with the
+   {} => By pressing '{take}' and a number (e.g '{take}1'), the conflicting file with the
                         number NUM is used as the new version. A copy of the
                         current file and the other conflicting files is put
-                        into the trash directory '{}'.
+                        into {trash}.
 
-   (M)ove to Trash   => By pressing 'm', all conflicting files are
-                        moved into the trash directory '{}'.
+   {} => By pressing '{trash_key}', all conflicting files are
+                        moved into {trash}.
 
-   Show (D)iff (NUM) => By pressing 'd' and a number (e.g 'd1'), the difference between the
-                        current file and the conflicting file NUM is shown.
+   {} => By pressing '{diff}' and a number (e.g '{diff}1'), the difference between the
+                        current file and the conflicting file NUM is shown. For binary files
+                        their properties - like size, hash and type - are compared instead,
+                        for SQLite databases the row counts of their tables and their schemas,
+                        for zip archives - like office documents - their entries, and the
+                        changed text entries are shown by the diff tool.
                         If there's only one conflicting file, then only pressing
-                        'd' is sufficient.
-                        By pressing 'd' and two numbers (e.g 'd1 2'), the difference between
+                        '{diff}' is sufficient.
+                        By pressing '{diff}' and two numbers (e.g '{diff}1 2'), the difference between
                         the two conflicting files is shown.
-                        The diff tool can be specified by the user by setting the environment
-                        variable 'CONFSOLVE_DIFF'. The default diff tool is 'gvimdiff -f'.
+                        By pressing '{diff}a', the files with the same contents are shown and
+                        the multi-way diff tool - if configured by 'diff_all' - is called
+                        with all files.
+                        The diff tool is '{diff_tool}', it can be set in the
+                        configuration or by the environment variable 'CONFSOLVE_DIFF'.
 
-   (S)kip            => By pressing 's', the current conflict is skipped
-                        and the next one is shown.
+   {} => By pressing '{merge_key}' and a number (e.g '{merge_key}1'), the merge tool is called
+                        with the current file and the conflicting file NUM, and the
+                        conflict is shown again afterwards. {merge}
 
-   (Q)uit            => By pressing 'q', the application is quit.
+   {} => By pressing '{combine_key}' and a number (e.g '{combine_key}1'), the current file and the
+                        conflicting file NUM are merged by their structure: JSON, YAML,
+                        TOML and INI files by their keys, calendars (.ics) and contacts
+                        (.vcf) by their entries, Markdown notes by their sections and
+                        paragraphs. Parts found in only one of the files are kept. For
+                        keys and paragraphs changed in both files it's asked which
+                        version to use, for entries the most recently modified one is
+                        used. The merge is written as the current file, the former
+                        files are put into {trash}.
 
-   (H)elp            => By pressing 'h', this help is printed.\n", dir_str, dir_str);
+   {} => By pressing '{skip}', the current conflict is skipped
+                        and the next one is shown.
 
-   Ok(())
+   {} => By pressing '{quit}', the application is quit.
+
+   {} => By pressing '{help}', this help is printed.\n",
+            label("Take File", keys.take, " (NUM)"), label("Move to Trash", keys.trash, ""),
+            label("Show Diff", keys.diff, " (NUM)"), label("Merge", keys.merge, " (NUM)"),
+            label("Combine", keys.combine, " (NUM)"), label("Skip", keys.skip, ""),
+            label("Quit", keys.quit, ""), label("Help", keys.help, ""),
+            take = keys.take, trash_key = keys.trash, diff = keys.diff, merge_key = keys.merge,
+            combine_key = keys.combine,
+            skip = keys.skip, quit = keys.quit, help = keys.help,
+            trash = trash, diff_tool = config.diff, merge = merge);
 }
@@ -0,0 +1,445 @@
+use std::fmt::{Display, Formatter, Error};
+use serde_json;
+use serde_yaml;
+use toml;
+
+use app_result::{AppResult, AppError};
+use super::{Side, MergeConflict, Merged, Choose};
+
+/// The format of a configuration file merged key by key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format
+{
+   Json,
+   Yaml,
+   Toml,
+   Ini
+}
+
+impl Display for Format
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Format::Json => write!(f, "JSON"),
+         Format::Yaml => write!(f, "YAML"),
+         Format::Toml => write!(f, "TOML"),
+         Format::Ini  => write!(f, "INI")
+      }
+   }
+}
+
+/// The format of a file with the lowercase extension `extension`.
+pub fn format_of(extension: &str) -> Option<Format>
+{
+   match extension {
+      "json"          => Some(Format::Json),
+      "yaml" | "yml"  => Some(Format::Yaml),
+      "toml"          => Some(Format::Toml),
+      "ini"           => Some(Format::Ini),
+      _               => None
+   }
+}
+
+/// Merges the files `current` and `conflicting` of `format` key by key. JSON, YAML and
+/// TOML files are written anew from the merged tree, so YAML and TOML files with comments
+/// aren't merged, INI files are merged line by line.
+pub fn merge(format: Format, current: &str, conflicting: &str, choose: &mut Choose) -> AppResult<Option<Merged>>
+{
+   if (format == Format::Yaml || format == Format::Toml) && (has_comments(current) || has_comments(conflicting)) {
+      return Err(AppError::from_string(format!(
+         "The {} files have comments, which would be lost by combining them, use a merge tool instead!", format)));
+   }
+
+   match format {
+      Format::Json => merge_as(format, current, conflicting, choose,
+                               |text| serde_json::from_str::<serde_json::Value>(text).map_err(|e| e.to_string()),
+                               |tree| serde_json::to_string_pretty(tree).map(|text| text + "\n").map_err(|e| e.to_string())),
+
+      Format::Yaml => merge_as(format, current, conflicting, choose,
+                               |text| serde_yaml::from_str::<serde_yaml::Value>(text).map_err(|e| e.to_string()),
+                               |tree| serde_yaml::to_string(tree).map(|text| without_yaml_header(&text)).map_err(|e| e.to_string())),
+
+      Format::Toml => merge_as(format, current, conflicting, choose,
+                               |text| toml::from_str::<toml::Value>(text).map_err(|e| e.to_string()),
+                               |tree| toml::to_string(tree).map_err(|e| e.to_string())),
+
+      Format::Ini  => merge_ini(current, conflicting, choose)
+   }
+}
+
+fn merge_as<T, P, W>(format: Format, current: &str, conflicting: &str, choose: &mut Choose, parse: P, write: W)
+   -> AppResult<Option<Merged>>
+   where T: Tree,
+         P: Fn(&str) -> Result<T, String>,
+         W: Fn(&T) -> Result<String, String>
+{
+   let current = parse(current)
+      .map_err(|err| AppError::from_string(format!("Couldn't parse the current file as {}: {}!", format, err)))?;
+
+   let conflicting = parse(conflicting)
+      .map_err(|err| AppError::from_string(format!("Couldn't parse the conflicting file as {}: {}!", format, err)))?;
+
+   let mut stats = Merged { text: String::new(), added: 0, conflicts: 0 };
+   let tree = match merge_trees("", &current, &conflicting, &mut stats, choose)? {
+      Some(tree) => tree,
+      None       => return Ok(None)
+   };
+
+   stats.text = write(&tree)
+      .map_err(|err| AppError::from_string(format!("Couldn't write the merged {}: {}!", format, err)))?;
+
+   Ok(Some(stats))
+}
+
+// A parsed configuration file, which is either a map with keys or any other value.
+trait Tree: Clone + PartialEq
+{
+   type Key: Clone + PartialEq;
+
+   /// The entries of a map, none for other values.
+   fn entries(&self) -> Option<Vec<(Self::Key, Self)>>;
+
+   /// A map of the same kind as `self` with `entries`.
+   fn with_entries(&self, entries: Vec<(Self::Key, Self)>) -> Self;
+
+   fn key_name(key: &Self::Key) -> String;
+
+   /// The value as shown to the user.
+   fn show(&self) -> String;
+}
+
+// Equal values are kept, maps are merged by their keys and the keys of only one
+// of them are kept, every other difference is a conflict decided by `choose`.
+fn merge_trees<T: Tree>(path: &str, current: &T, conflicting: &T, stats: &mut Merged, choose: &mut Choose)
+   -> AppResult<Option<T>>
+{
+   if current == conflicting {
+      return Ok(Some(current.clone()));
+   }
+
+   if let (Some(cur_entries), Some(conf_entries)) = (current.entries(), conflicting.entries()) {
+      let mut merged = Vec::new();
+      for (key, value) in cur_entries.iter() {
+         let entry_path = if path.is_empty() { T::key_name(key) } else { format!("{}.{}", path, T::key_name(key)) };
+         match conf_entries.iter().find(|&(k, _)| k == key) {
+            Some((_, other)) => {
+               match merge_trees(&entry_path, value, other, stats, choose)? {
+                  Some(value) => merged.push((key.clone(), value)),
+                  None        => return Ok(None)
+               }
+            }
+
+            None => merged.push((key.clone(), value.clone()))
+         }
+      }
+
+      for (key, value) in conf_entries {
+         if ! cur_entries.iter().any(|(k, _)| *k == key) {
+            merged.push((key, value));
+            stats.added += 1;
+         }
+      }
+
+      return Ok(Some(current.with_entries(merged)));
+   }
+
+   stats.conflicts += 1;
+   let conflict = MergeConflict {
+      what       : if path.is_empty() { "the whole file".to_string() } else { path.to_string() },
+      current    : current.show(),
+      conflicting: conflicting.show()
+   };
+
+   match choose(&conflict)? {
+      Some(Side::Current)     => Ok(Some(current.clone())),
+      Some(Side::Conflicting) => Ok(Some(conflicting.clone())),
+      None                    => Ok(None)
+   }
+}
+
+impl Tree for serde_json::Value
+{
+   type Key = String;
+
+   fn entries(&self) -> Option<Vec<(String, Self)>>
+   {
+      self.as_object().map(|map| map.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+   }
+
+   fn with_entries(&self, entries: Vec<(String, Self)>) -> Self
+   {
+      serde_json::Value::Object(entries.into_iter().collect())
+   }
+
+   fn key_name(key: &String) -> String
+   {
+      key.clone()
+   }
+
+   fn show(&self) -> String
+   {
+      self.to_string()
+   }
+}
+
+impl Tree for serde_yaml::Value
+{
+   type Key = serde_yaml::Value;
+
+   fn entries(&self) -> Option<Vec<(Self, Self)>>
+   {
+      self.as_mapping().map(|map| map.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+   }
+
+   fn with_entries(&self, entries: Vec<(Self, Self)>) -> Self
+   {
+      serde_yaml::Value::Mapping(entries.into_iter().collect())
+   }
+
+   fn key_name(key: &Self) -> String
+   {
+      match *key {
+         serde_yaml::Value::String(ref key) => key.clone(),
+         ref key                            => key.show()
+      }
+   }
+
+   fn show(&self) -> String
+   {
+      serde_yaml::to_string(self)
+         .map(|text| without_yaml_header(&text).trim_end().to_string())
+         .unwrap_or_default()
+   }
+}
+
+// Serialized YAML documents start with a `---` line, which most configuration files don't have.
+fn without_yaml_header(text: &str) -> String
+{
+   text.trim_start_matches("---\n").to_string()
+}
+
+impl Tree for toml::Value
+{
+   type Key = String;
+
+   fn entries(&self) -> Option<Vec<(String, Self)>>
+   {
+      self.as_table().map(|table| table.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+   }
+
+   fn with_entries(&self, entries: Vec<(String, Self)>) -> Self
+   {
+      toml::Value::Table(entries.into_iter().collect())
+   }
+
+   fn key_name(key: &String) -> String
+   {
+      key.clone()
+   }
+
+   fn show(&self) -> String
+   {
+      self.to_string().trim_end().to_string()
+   }
+}
+
+// A line of an INI file with the section it's in, the lines before
+// the first section are in the section with the empty name.
+#[derive(Clone)]
+struct IniLine
+{
+   text   : String,
+   section: String,
+
+   /// the key and the value of a `key = value` line
+   entry  : Option<(String, String)>
+}
+
+impl IniLine
+{
+   fn is_key(&self, section: &str, key: &str) -> bool
+   {
+      self.section == section && self.entry.as_ref().map(|(k, _)| k == key).unwrap_or(false)
+   }
+
+   fn is_header(&self, section: &str) -> bool
+   {
+      self.section == section && self.entry.is_none() && self.text.trim().starts_with('[')
+   }
+}
+
+// Parses the lines of an INI file, comments start with `;` or `#`.
+fn parse_ini(text: &str) -> Result<Vec<IniLine>, String>
+{
+   let mut lines = Vec::new();
+   let mut section = String::new();
+   for (num, text) in text.lines().enumerate() {
+      let line = text.trim().trim_start_matches('\u{feff}');
+      let mut entry = None;
+      if line.starts_with('[') && line.ends_with(']') {
+         section = line[1..line.len() - 1].trim().to_string();
+      } else if ! line.is_empty() && ! line.starts_with(';') && ! line.starts_with('#') {
+         match line.find('=') {
+            Some(pos) => entry = Some((line[..pos].trim().to_string(), line[pos + 1..].trim().to_string())),
+            None      => return Err(format!("line {} is neither a section, a key nor a comment", num + 1))
+         }
+      }
+
+      lines.push(IniLine { text: text.to_string(), section: section.clone(), entry });
+   }
+
+   Ok(lines)
+}
+
+// INI files are merged line by line, so that their comments and formatting are kept.
+// A repeated key - like the multiple values of a key in a systemd unit - is merged
+// with all of its values, as if they were one value.
+fn merge_ini(current: &str, conflicting: &str, choose: &mut Choose) -> AppResult<Option<Merged>>
+{
+   let mut lines = parse_ini(current)
+      .map_err(|err| AppError::from_string(format!("Couldn't parse the current file as INI: {}!", err)))?;
+
+   let other = parse_ini(conflicting)
+      .map_err(|err| AppError::from_string(format!("Couldn't parse the conflicting file as INI: {}!", err)))?;
+
+   let has_section = |lines: &[IniLine], section: &str| section.is_empty() || lines.iter().any(|l| l.section == section);
+   let mut stats = Merged { text: String::new(), added: 0, conflicts: 0 };
+   let mut merged_keys = Vec::new();
+   for line in other.iter() {
+      let (section, key) = match line.entry {
+         Some((ref key, _)) => (line.section.as_str(), key.as_str()),
+         None               => continue
+      };
+
+      // the sections only in the conflicting file are added as a whole below
+      if merged_keys.contains(&(section, key)) || ! has_section(&lines, section) {
+         continue;
+      }
+
+      merged_keys.push((section, key));
+      let other_lines = other.iter().filter(|l| l.is_key(section, key)).cloned().collect::<Vec<IniLine>>();
+      let positions = (0..lines.len()).filter(|&i| lines[i].is_key(section, key)).collect::<Vec<usize>>();
+      if positions.is_empty() {
+         // after the last key of the section, otherwise after its header
+         let pos = (0..lines.len()).rev()
+            .find(|&i| lines[i].section == section && lines[i].entry.is_some())
+            .or_else(|| (0..lines.len()).rev().find(|&i| lines[i].is_header(section)))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+
+         lines.splice(pos..pos, other_lines);
+         stats.added += 1;
+         continue;
+      }
+
+      let values = |lines: &[IniLine]| {
+         lines.iter().filter(|l| l.is_key(section, key)).map(|l| l.entry.clone().unwrap_or_default().1).collect::<Vec<String>>()
+      };
+
+      let (cur_values, other_values) = (values(&lines), values(&other_lines));
+      if cur_values == other_values {
+         continue;
+      }
+
+      stats.conflicts += 1;
+      let conflict = MergeConflict {
+         what       : if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) },
+         current    : cur_values.join("\n"),
+         conflicting: other_values.join("\n")
+      };
+
+      match choose(&conflict)? {
+         Some(Side::Current)     => (),
+         Some(Side::Conflicting) => {
+            for &pos in positions.iter().rev() {
+               lines.remove(pos);
+            }
+
+            lines.splice(positions[0]..positions[0], other_lines);
+         }
+
+         None => return Ok(None)
+      }
+   }
+
+   let mut added_sections = Vec::new();
+   for line in other.iter() {
+      if added_sections.contains(&line.section) || has_section(&lines, &line.section) {
+         continue;
+      }
+
+      if lines.last().map(|l| ! l.text.trim().is_empty()).unwrap_or(false) {
+         lines.push(IniLine { text: String::new(), section: String::new(), entry: None });
+      }
+
+      lines.extend(other.iter().filter(|l| l.section == line.section).cloned());
+      added_sections.push(line.section.clone());
+      stats.added += 1;
+   }
+
+   let newline = if current.contains("\r\n") { "\r\n" } else { "\n" };
+   stats.text = lines.iter().map(|l| format!("{}{}", l.text, newline)).collect();
+   Ok(Some(stats))
+}
+
+// If the YAML or TOML `text` has comments, which would be lost by writing the merged tree.
+// A `#` inside of a string might also be taken for a comment, then the files aren't merged either.
+fn has_comments(text: &str) -> bool
+{
+   text.lines().any(|line| line.trim_start().starts_with('#') || line.contains(" #") || line.contains("\t#"))
+}
+
+#[test]
+fn tests()
+{
+   let mut asked = Vec::new();
+   let merged = {
+      let mut choose = |conflict: &MergeConflict| {
+         asked.push((conflict.what.clone(), conflict.current.clone(), conflict.conflicting.clone()));
+         Ok(Some(Side::Conflicting))
+      };
+
+      merge(Format::Json,
+            r#"{"editor": {"font": 12, "theme": "dark"}, "files": [1]}"#,
+            r#"{"editor": {"font": 14, "theme": "dark", "tabs": 3}, "files": [1], "new": true}"#,
+            &mut choose).unwrap().unwrap()
+   };
+
+   assert_eq!(asked, [("editor.font".to_string(), "12".to_string(), "14".to_string())]);
+   assert_eq!((merged.added, merged.conflicts), (2, 1));
+   assert_eq!(serde_json::from_str::<serde_json::Value>(&merged.text).unwrap(),
+              serde_json::from_str::<serde_json::Value>(r#"{"editor": {"font": 14, "theme": "dark", "tabs": 3}, "files": [1], "new": true}"#).unwrap());
+
+   let mut abort = |_: &MergeConflict| Ok(None);
+   assert!(merge(Format::Yaml, "a: 1\nb: 2\n", "a: 1\nb: 3\n", &mut abort).unwrap().is_none());
+
+   let merged = merge(Format::Yaml, "a: 1\nb: [x]\n", "a: 1\nc: {d: 2}\n", &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, "a: 1\nb:\n  - x\nc:\n  d: 2\n");
+
+   let merged = merge(Format::Toml, "a = 1\n[t]\nx = \"y\"\n", "b = 2\n[t]\nx = \"y\"\nz = 3\n", &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, "a = 1\nb = 2\n\n[t]\nx = \"y\"\nz = 3\n");
+
+   assert!(merge(Format::Yaml, "# comment\na: 1\n", "a: 1\n", &mut abort).is_err());
+   assert!(merge(Format::Toml, "a = 1\n", "a = 1 # comment\n", &mut abort).is_err());
+
+   // the comments and the repeated keys of INI files are kept
+   let merged = merge(Format::Ini, "; comment\nglobal = 1\n[main]\na = 1\npath = x\npath = y\n\n[last]\n",
+                      "global = 1\n[main]\nb = 2\npath = x\npath = y\n[other]\n# other comment\nc=3\n[last]\nd = 4\n", &mut abort)
+      .unwrap().unwrap();
+
+   assert_eq!(merged.text, "; comment\nglobal = 1\n[main]\na = 1\npath = x\npath = y\nb = 2\n\n[last]\nd = 4\n\n\
+                            [other]\n# other comment\nc=3\n");
+   assert_eq!((merged.added, merged.conflicts), (3, 0));
+
+   let mut take_conflicting = |_: &MergeConflict| Ok(Some(Side::Conflicting));
+   let merged = merge(Format::Ini, "[s]\n; paths\npath = x\na = 1\npath = y\n", "[s]\npath = x\npath = z\n", &mut take_conflicting)
+      .unwrap().unwrap();
+
+   assert_eq!(merged.text, "[s]\n; paths\npath = x\npath = z\na = 1\n");
+   assert!(merge(Format::Ini, "a = 1\nnonsense\n", "", &mut abort).is_err());
+   assert!(merge(Format::Json, "{", "{}", &mut abort).is_err());
+
+   assert_eq!(format_of("yml"), Some(Format::Yaml));
+   assert_eq!(format_of("txt"), None);
+}
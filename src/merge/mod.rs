@@ -0,0 +1,102 @@
+use std::path::Path;
+use std::fmt::{Display, Formatter, Error};
+
+use app_result::AppResult;
+
+pub use self::keys::Format;
+pub use self::records::RecordFormat;
+
+mod keys;
+mod records;
+mod notes;
+
+// The built-in merges combine the current file of a conflict with one of its
+// conflicting files. Without the common ancestor of both files a change can't
+// be told apart from an addition on the other side, so everything found in only
+// one of the files is kept and only the parts changed in both are asked for.
+
+/// How the files of a conflict are merged, chosen by their extension.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Merger
+{
+   /// configuration files, key by key
+   Keys(Format),
+
+   /// calendars and address books, entry by entry
+   Records(RecordFormat),
+
+   /// Markdown notes, by their front matter, sections and blocks
+   Notes
+}
+
+impl Display for Merger
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Merger::Keys(format)    => write!(f, "{} keys", format),
+         Merger::Records(format) => write!(f, "{}", format),
+         Merger::Notes           => write!(f, "notes")
+      }
+   }
+}
+
+/// The file whose version of a conflicting part is used.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Side
+{
+   Current,
+   Conflicting
+}
+
+/// A part of the files, which has been changed in both of them.
+pub struct MergeConflict
+{
+   /// where the part is, e.g. `editor.fontSize`
+   pub what: String,
+
+   /// the part in the current file
+   pub current: String,
+
+   /// the part in the conflicting file
+   pub conflicting: String
+}
+
+/// The result of a merge.
+pub struct Merged
+{
+   /// the merged contents
+   pub text: String,
+
+   /// the number of parts, which have only been in the conflicting file
+   pub added: usize,
+
+   /// the number of parts, which have been changed in both files
+   pub conflicts: usize
+}
+
+/// Asks which version of a conflicting part should be used, none to abort the merge.
+pub type Choose<'a> = dyn FnMut(&MergeConflict) -> AppResult<Option<Side>> + 'a;
+
+/// The built-in merge for `file`, none if there isn't one for its type.
+pub fn merger_for(file: &Path) -> Option<Merger>
+{
+   let extension = file.extension()?.to_string_lossy().to_lowercase();
+   keys::format_of(&extension).map(Merger::Keys)
+      .or_else(|| records::format_of(&extension).map(Merger::Records))
+      .or_else(|| if notes::is_note(&extension) { Some(Merger::Notes) } else { None })
+}
+
+impl Merger
+{
+   /// Merges the contents `current` of the current file with `conflicting` of a conflicting
+   /// file, asking by `choose` for the parts changed in both. None if the merge has been aborted.
+   pub fn merge(&self, current: &str, conflicting: &str, choose: &mut Choose) -> AppResult<Option<Merged>>
+   {
+      match *self {
+         Merger::Keys(format)    => keys::merge(format, current, conflicting, choose),
+         Merger::Records(format) => records::merge(format, current, conflicting, choose),
+         Merger::Notes           => notes::merge(current, conflicting, choose)
+      }
+   }
+}
@@ -0,0 +1,430 @@
+use std::iter;
+
+use app_result::AppResult;
+use super::{Side, MergeConflict, Merged, Choose};
+use super::keys::{self, Format};
+
+// Markdown notes - like the ones of Obsidian or Joplin - are merged by their structure:
+//
+//    - the YAML front matter between `---` lines is merged key by key
+//    - the sections are matched by their headings, sections of only one note are kept
+//    - inside of a section the blocks - paragraphs, list items and code blocks - of
+//      both notes are aligned, the list items of both notes are kept, and of checklist
+//      items differing only in their state the checked one is used
+//    - a paragraph edited in both notes - a similar, but not the same paragraph at the
+//      same place - is asked for, the other paragraphs of both notes are kept
+//
+// The lines of the blocks and the blank lines between them are kept unchanged.
+
+/// The lowercase extensions of notes.
+pub fn is_note(extension: &str) -> bool
+{
+   extension == "md" || extension == "markdown"
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Kind
+{
+   Heading,
+   Paragraph,
+   Item,
+   Code
+}
+
+#[derive(Clone, Debug)]
+struct Block
+{
+   kind: Kind,
+
+   /// the number of blank lines before the block
+   blank_lines: usize,
+
+   lines: Vec<String>
+}
+
+impl Block
+{
+   fn text(&self) -> String
+   {
+      self.lines.join("\n")
+   }
+
+   /// The text by which the block is matched, a checklist item regardless of its state.
+   fn key(&self) -> String
+   {
+      if self.is_checked() {
+         let marker = item_marker_len(&self.lines[0]).unwrap_or(0);
+         format!("{}[ ]{}", &self.lines[0][..marker], &self.text()[marker + 3..])
+      } else {
+         self.text()
+      }
+   }
+
+   fn is_checked(&self) -> bool
+   {
+      self.kind == Kind::Item && item_marker_len(&self.lines[0])
+         .map(|marker| self.lines[0][marker..].starts_with("[x]") || self.lines[0][marker..].starts_with("[X]"))
+         .unwrap_or(false)
+   }
+}
+
+struct Section
+{
+   /// none for the blocks before the first heading
+   heading: Option<Block>,
+
+   blocks: Vec<Block>
+}
+
+impl Section
+{
+   fn title(&self) -> Option<String>
+   {
+      self.heading.as_ref().map(|heading| heading.text().trim().to_string())
+   }
+}
+
+struct Note
+{
+   /// the YAML between the `---` lines
+   front_matter: Option<String>,
+
+   sections: Vec<Section>,
+
+   /// the number of blank lines at the end
+   blank_lines: usize
+}
+
+fn parse(text: &str) -> Note
+{
+   let mut lines = text.lines().peekable();
+   let mut front_matter = None;
+   if text.starts_with("---\n") {
+      let yaml = text.lines().skip(1).take_while(|line| *line != "---" && *line != "...").collect::<Vec<&str>>();
+      if text.lines().nth(yaml.len() + 1).is_some() {
+         front_matter = Some(yaml.join("\n"));
+         lines.nth(yaml.len() + 1);
+      }
+   }
+
+   let mut sections = vec![Section { heading: None, blocks: Vec::new() }];
+   let mut blank_lines = 0;
+   while let Some(line) = lines.next() {
+      if line.trim().is_empty() {
+         blank_lines += 1;
+         continue;
+      }
+
+      let mut block = Block { kind: Kind::Paragraph, blank_lines, lines: vec![line.to_string()] };
+      blank_lines = 0;
+
+      if let Some(fence) = fence(line) {
+         block.kind = Kind::Code;
+         for line in lines.by_ref() {
+            block.lines.push(line.to_string());
+            if line.trim_start().starts_with(fence) {
+               break;
+            }
+         }
+      } else if is_heading(line) {
+         block.kind = Kind::Heading;
+         sections.push(Section { heading: Some(block), blocks: Vec::new() });
+         continue;
+      } else if item_marker_len(line).is_some() {
+         // the indented lines after an item, like nested items, belong to it
+         block.kind = Kind::Item;
+         while let Some(line) = lines.next_if(|l| l.starts_with([' ', '\t']) && ! l.trim().is_empty()) {
+            block.lines.push(line.to_string());
+         }
+      } else {
+         while let Some(line) = lines.next_if(|l| ! l.trim().is_empty() && ! starts_block(l)) {
+            block.lines.push(line.to_string());
+         }
+      }
+
+      sections.last_mut().unwrap().blocks.push(block);
+   }
+
+   Note { front_matter, sections, blank_lines }
+}
+
+fn write(note: &Note, newline: &str) -> String
+{
+   let mut lines = Vec::new();
+   if let Some(ref yaml) = note.front_matter {
+      lines.push("---".to_string());
+      lines.extend(yaml.lines().map(|l| l.to_string()));
+      lines.push("---".to_string());
+   }
+
+   for section in note.sections.iter() {
+      for block in section.heading.iter().chain(section.blocks.iter()) {
+         lines.resize(lines.len() + block.blank_lines, String::new());
+         lines.extend(block.lines.iter().cloned());
+      }
+   }
+
+   lines.resize(lines.len() + note.blank_lines, String::new());
+   lines.join(newline) + newline
+}
+
+fn starts_block(line: &str) -> bool
+{
+   fence(line).is_some() || is_heading(line) || item_marker_len(line).is_some()
+}
+
+fn fence(line: &str) -> Option<&'static str>
+{
+   let line = line.trim_start();
+   ["```", "~~~"].iter().find(|fence| line.starts_with(*fence)).cloned()
+}
+
+// A heading like `## Tasks`, but not a tag like `#tasks`.
+fn is_heading(line: &str) -> bool
+{
+   let rest = line.trim_start_matches('#');
+   line.starts_with('#') && line.len() - rest.len() <= 6 && (rest.is_empty() || rest.starts_with(' '))
+}
+
+// The length of the marker of a list item, like `- ` or `1. `.
+fn item_marker_len(line: &str) -> Option<usize>
+{
+   if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+      return Some(2);
+   }
+
+   let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+   let rest = &line[digits..];
+   if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) { Some(digits + 2) } else { None }
+}
+
+/// Merges the notes `current` and `conflicting`.
+pub fn merge(current: &str, conflicting: &str, choose: &mut Choose) -> AppResult<Option<Merged>>
+{
+   let newline = if current.contains("\r\n") { "\r\n" } else { "\n" };
+   let mut note = parse(&current.replace("\r\n", "\n"));
+   let other = parse(&conflicting.replace("\r\n", "\n"));
+   let mut stats = Merged { text: String::new(), added: 0, conflicts: 0 };
+
+   note.front_matter = match (note.front_matter.take(), other.front_matter) {
+      (Some(cur), Some(conf)) => {
+         if cur == conf {
+            Some(cur)
+         } else {
+            match merge_front_matter(&cur, &conf, &mut stats, choose)? {
+               Some(yaml) => Some(yaml),
+               None       => return Ok(None)
+            }
+         }
+      }
+
+      (cur, conf) => cur.or(conf)
+   };
+
+   let mut used = vec![false; note.sections.len()];
+   let mut last = 0;
+   for section in other.sections {
+      let title = section.title();
+      match (0..note.sections.len()).find(|&i| ! used[i] && note.sections[i].title() == title) {
+         Some(i) => {
+            let blocks = match merge_blocks(&note.sections[i], &section.blocks, &mut stats, choose)? {
+               Some(blocks) => blocks,
+               None         => return Ok(None)
+            };
+
+            note.sections[i].blocks = blocks;
+            used[i] = true;
+            last = i;
+         }
+
+         // a new section is put after the one it follows in the conflicting note
+         None => {
+            last += 1;
+            note.sections.insert(last, section);
+            used.insert(last, true);
+            stats.added += 1;
+         }
+      }
+   }
+
+   stats.text = write(&note, newline);
+   Ok(Some(stats))
+}
+
+// The front matter is merged like a YAML file, if it can't be parsed the user chooses one of them.
+fn merge_front_matter(current: &str, conflicting: &str, stats: &mut Merged, choose: &mut Choose) -> AppResult<Option<String>>
+{
+   if let Ok(merged) = keys::merge(Format::Yaml, current, conflicting, choose) {
+      return Ok(merged.map(|merged| {
+         stats.added += merged.added;
+         stats.conflicts += merged.conflicts;
+         merged.text.trim_end().to_string()
+      }));
+   }
+
+   stats.conflicts += 1;
+   let conflict = MergeConflict {
+      what       : "the front matter".to_string(),
+      current    : current.to_string(),
+      conflicting: conflicting.to_string()
+   };
+
+   Ok(choose(&conflict)?.map(|side| if side == Side::Current { current.to_string() } else { conflicting.to_string() }))
+}
+
+// Merges the blocks of `section` with `other`. The blocks in both are aligned by their longest
+// common subsequence, the blocks between the aligned ones are merged by `merge_gap`.
+fn merge_blocks(section: &Section, other: &[Block], stats: &mut Merged, choose: &mut Choose) -> AppResult<Option<Vec<Block>>>
+{
+   let current = &section.blocks;
+   let mut merged = Vec::new();
+   let (mut i, mut j) = (0, 0);
+   for (ci, cj) in common_blocks(current, other).into_iter().chain(iter::once((current.len(), other.len()))) {
+      if ! merge_gap(section, &current[i..ci], &other[j..cj], &mut merged, stats, choose)? {
+         return Ok(None);
+      }
+
+      if ci < current.len() {
+         let checked = other[cj].is_checked() && ! current[ci].is_checked();
+         let lines = if checked { other[cj].lines.clone() } else { current[ci].lines.clone() };
+         merged.push(Block { lines, ..current[ci].clone() });
+      }
+
+      i = ci + 1;
+      j = cj + 1;
+   }
+
+   Ok(Some(merged))
+}
+
+// Merges the blocks `current` and `other`, which are between the same aligned blocks. A
+// paragraph of `current` is paired with the first similar one of `other`, and the user is
+// asked which one to use. The unpaired blocks of both are kept. False if the user aborted.
+fn merge_gap(section: &Section, current: &[Block], other: &[Block], merged: &mut Vec<Block>,
+             stats: &mut Merged, choose: &mut Choose) -> AppResult<bool>
+{
+   let mut used = vec![false; other.len()];
+   for block in current.iter() {
+      let paired = (0..other.len()).find(|&j| {
+         ! used[j] && block.kind != Kind::Item && other[j].kind == block.kind && is_similar(block, &other[j])
+      });
+
+      let replacement = match paired {
+         Some(j) => { used[j] = true; &other[j] }
+         None    => { merged.push(block.clone()); continue; }
+      };
+
+      stats.conflicts += 1;
+      let conflict = MergeConflict {
+         what: match section.title() {
+            Some(title) => format!("a paragraph in '{}'", title),
+            None        => "a paragraph before the first heading".to_string()
+         },
+         current    : block.text(),
+         conflicting: replacement.text()
+      };
+
+      match choose(&conflict)? {
+         Some(Side::Current)     => merged.push(block.clone()),
+         Some(Side::Conflicting) => merged.push(Block { blank_lines: block.blank_lines, ..replacement.clone() }),
+         None                    => return Ok(false)
+      }
+   }
+
+   for (block, _) in other.iter().zip(used).filter(|&(_, used)| ! used) {
+      merged.push(block.clone());
+      stats.added += 1;
+   }
+
+   Ok(true)
+}
+
+// If the blocks are versions of the same paragraph: they share at least half of their
+// words, or the shorter one is at least by half the same at the start of the longer one.
+fn is_similar(a: &Block, b: &Block) -> bool
+{
+   let words = |block: &Block| -> Vec<String> {
+      block.text().split(|c: char| ! c.is_alphanumeric()).filter(|w| ! w.is_empty()).map(|w| w.to_lowercase()).collect()
+   };
+
+   let (a_words, b_words) = (words(a), words(b));
+   let shared = a_words.iter().filter(|w| b_words.contains(w)).count();
+   if shared > 0 && shared * 2 >= a_words.len().max(b_words.len()) {
+      return true;
+   }
+
+   let (a_text, b_text) = (a.text(), b.text());
+   let prefix = a_text.chars().zip(b_text.chars()).take_while(|(x, y)| x == y).count();
+   prefix > 0 && prefix * 2 >= a_text.chars().count().min(b_text.chars().count())
+}
+
+// The indices of the longest common subsequence of the blocks `a` and `b`, compared by their keys.
+fn common_blocks(a: &[Block], b: &[Block]) -> Vec<(usize, usize)>
+{
+   let (a, b) = (a.iter().map(|x| x.key()).collect::<Vec<String>>(), b.iter().map(|x| x.key()).collect::<Vec<String>>());
+   let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+   for i in (0..a.len()).rev() {
+      for j in (0..b.len()).rev() {
+         lengths[i][j] = if a[i] == b[j] { lengths[i + 1][j + 1] + 1 } else { lengths[i + 1][j].max(lengths[i][j + 1]) };
+      }
+   }
+
+   let mut common = Vec::new();
+   let (mut i, mut j) = (0, 0);
+   while i < a.len() && j < b.len() {
+      if a[i] == b[j] {
+         common.push((i, j));
+         i += 1;
+         j += 1;
+      } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+         i += 1;
+      } else {
+         j += 1;
+      }
+   }
+
+   common
+}
+
+#[test]
+fn tests()
+{
+   let current = "---\ntitle: Plans\ntags: [a]\n---\n\n# Plans\n\nWe meet on Monday.\n\n## Tasks\n\n- [ ] buy milk\n- [ ] call Dan\n\n\
+                  Some notes.\n";
+   let conflicting = "---\ntitle: Plans\ntags: [a]\n---\n\n# Plans\n\nWe meet on Tuesday.\n\n## Tasks\n\n- [x] buy milk\n- [ ] call Dan\n\
+                      - [ ] water plants\n\nSome notes.\n\n## Ideas\n\n* a garden\n";
+
+   let mut asked = Vec::new();
+   let merged = {
+      let mut choose = |conflict: &MergeConflict| {
+         asked.push((conflict.what.clone(), conflict.current.clone(), conflict.conflicting.clone()));
+         Ok(Some(Side::Conflicting))
+      };
+
+      merge(current, conflicting, &mut choose).unwrap().unwrap()
+   };
+
+   assert_eq!(asked, [("a paragraph in '# Plans'".to_string(), "We meet on Monday.".to_string(), "We meet on Tuesday.".to_string())]);
+   assert_eq!(merged.text, conflicting);
+   assert_eq!((merged.added, merged.conflicts), (2, 1));
+
+   // an unchanged note is written unchanged
+   let mut abort = |_: &MergeConflict| Ok(None);
+   let text = "Intro\n\n\n```\n# not a heading\n\n```\n#tag\n1. one\n   more\n2) two\n";
+   assert_eq!(merge(text, text, &mut abort).unwrap().unwrap().text, text);
+
+   let merged = merge("---\na: 1\n---\nText\n", "---\nb: 2\n---\nText\n\nMore text\n", &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, "---\na: 1\nb: 2\n---\nText\n\nMore text\n");
+
+   assert!(merge("# A\n\nThe cat sat.\n", "# A\n\nThe cat sat down.\n", &mut abort).unwrap().is_none());
+
+   // paragraphs added at the same place in both notes are kept
+   let merged = merge("# A\n\nOne\n\nMine\n", "# A\n\nOne\n\nTheirs\n", &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, "# A\n\nOne\n\nMine\n\nTheirs\n");
+   assert_eq!((merged.added, merged.conflicts), (1, 0));
+
+   let merged = merge("# A\n\nMine\n", "# A\n\nThe cat sat.\n\nTheirs\n", &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, "# A\n\nMine\n\nThe cat sat.\n\nTheirs\n");
+
+   assert!(is_heading("## A") && ! is_heading("#a") && ! is_heading("####### A"));
+}
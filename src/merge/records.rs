@@ -0,0 +1,411 @@
+use std::fmt::{Display, Formatter, Error};
+
+use app_result::AppResult;
+use date_time::DateTime;
+use super::{Side, MergeConflict, Merged, Choose};
+
+// Calendars (RFC 5545) and address books (RFC 6350) are lists of records - events,
+// tasks, contacts - between `BEGIN:<NAME>` and `END:<NAME>` lines. The records are
+// identified by their `UID`, so the merge is the union of the records of both files.
+// A record in both files, which differs, is taken from the file where it has been
+// modified last, by its `LAST-MODIFIED` or `REV` timestamp. Only if it's unknown
+// which one is newer the user is asked.
+//
+// The lines of the records are kept unchanged, also if they're folded.
+//
+// The timestamps are compared in UTC. Timestamps without a UTC offset are in an
+// unknown time zone, so they're only compared with each other, otherwise the user is asked.
+
+/// The format of a file merged record by record.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecordFormat
+{
+   /// iCalendar, `.ics`
+   Calendar,
+
+   /// vCard, `.vcf`
+   Contacts
+}
+
+impl Display for RecordFormat
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         RecordFormat::Calendar => write!(f, "calendar entries"),
+         RecordFormat::Contacts => write!(f, "contacts")
+      }
+   }
+}
+
+/// The format of a file with the lowercase extension `extension`.
+pub fn format_of(extension: &str) -> Option<RecordFormat>
+{
+   match extension {
+      "ics"           => Some(RecordFormat::Calendar),
+      "vcf" | "vcard" => Some(RecordFormat::Contacts),
+      _               => None
+   }
+}
+
+impl RecordFormat
+{
+   // The components, which are records. Other components - like the alarms of
+   // an event - are part of the record they're in.
+   fn records(&self) -> &'static [&'static str]
+   {
+      match *self {
+         RecordFormat::Calendar => &["VEVENT", "VTODO", "VJOURNAL", "VFREEBUSY", "VTIMEZONE"],
+         RecordFormat::Contacts => &["VCARD"]
+      }
+   }
+
+   // The property with the time of the last modification of a record.
+   fn modified(&self) -> &'static str
+   {
+      match *self {
+         RecordFormat::Calendar => "LAST-MODIFIED",
+         RecordFormat::Contacts => "REV"
+      }
+   }
+}
+
+/// A record with its unchanged lines.
+struct Record
+{
+   /// the component, e.g. `VEVENT`
+   name: String,
+
+   /// the properties with their unfolded values
+   properties: Vec<(String, String)>,
+
+   lines: Vec<String>
+}
+
+impl Record
+{
+   fn property(&self, name: &str) -> Option<&str>
+   {
+      self.properties.iter().find(|&(n, _)| n == name).map(|(_, value)| value.as_str())
+   }
+
+   // The identity of the record: its UID - and the RECURRENCE-ID of a changed occurrence
+   // of a recurring event - the TZID of a time zone, otherwise the whole record.
+   fn identity(&self) -> String
+   {
+      match (self.property("UID"), self.property("TZID")) {
+         (Some(uid), _)     => format!("{} {}", uid, self.property("RECURRENCE-ID").unwrap_or_default()),
+         (None, Some(tzid)) => format!("TZID {}", tzid),
+         (None, None)       => self.properties.iter().map(|(n, v)| format!("{}:{}\n", n, v)).collect()
+      }
+   }
+
+   // The time of the last modification, none if it's missing or invalid.
+   fn modified(&self, format: RecordFormat) -> Option<Timestamp>
+   {
+      self.property(format.modified()).and_then(parse_timestamp)
+   }
+
+   fn describe(&self) -> String
+   {
+      let kind = match self.name.as_str() {
+         "VEVENT"    => "event",
+         "VTODO"     => "task",
+         "VJOURNAL"  => "journal entry",
+         "VTIMEZONE" => "time zone",
+         "VCARD"     => "contact",
+         _           => "entry"
+      };
+
+      match self.property("SUMMARY").or_else(|| self.property("FN")).or_else(|| self.property("TZID")) {
+         Some(title) => format!("the {} '{}'", kind, title),
+         None        => format!("the {} {}", kind, self.property("UID").unwrap_or_default())
+      }
+   }
+
+   fn show(&self) -> String
+   {
+      self.properties.iter()
+         .filter(|&(name, _)| name != "BEGIN" && name != "END")
+         .map(|(name, value)| format!("{}:{}", name, value))
+         .collect::<Vec<String>>()
+         .join("\n")
+   }
+}
+
+/// A `LAST-MODIFIED` or `REV` timestamp.
+#[derive(PartialEq, PartialOrd, Debug)]
+struct Timestamp
+{
+   /// the seconds since 1970 in UTC, or in local time if `is_utc` isn't set
+   seconds: i64,
+
+   /// the fraction of the second
+   nanos  : u32,
+
+   /// if the time is in UTC or has a UTC offset
+   is_utc : bool
+}
+
+// Parses the basic (`20150601T100000Z`) and the extended (`2015-06-01T12:00:00.5+02:00`)
+// forms of ISO 8601, with an optional UTC offset and fraction of the second, or only a date.
+fn parse_timestamp(input: &str) -> Option<Timestamp>
+{
+   let input = input.trim();
+   let (date, time) = match input.find(['T', 't']) {
+      Some(pos) => (&input[..pos], &input[pos + 1..]),
+      None      => (input, "000000")
+   };
+
+   let (time, zone) = match time.find(['Z', 'z', '+', '-']) {
+      Some(pos) => (&time[..pos], Some(&time[pos..])),
+      None      => (time, None)
+   };
+
+   let (time, fraction) = match time.find(['.', ',']) {
+      Some(pos) => (&time[..pos], &time[pos + 1..]),
+      None      => (time, "")
+   };
+
+   let digits = |text: &str, sep: char| -> Option<Vec<u32>> {
+      let text = text.replace(sep, "");
+      if ! text.chars().all(|c| c.is_ascii_digit()) {
+         return None;
+      }
+
+      Some(text.chars().filter_map(|c| c.to_digit(10)).collect())
+   };
+
+   let number = |digits: &[u32]| digits.iter().fold(0, |num, d| num * 10 + d);
+   let (date, time, fraction) = (digits(date, '-')?, digits(time, ':')?, digits(fraction, ' ')?);
+   if date.len() != 8 || time.len() != 6 || fraction.len() > 9 {
+      return None;
+   }
+
+   let date_time = DateTime {
+      year  : number(&date[..4]) as i32,
+      month : number(&date[4..6]),
+      day   : number(&date[6..]),
+      hour  : number(&time[..2]),
+      minute: number(&time[2..4]),
+      second: number(&time[4..])
+   };
+
+   if ! (1..=12).contains(&date_time.month) || ! (1..=31).contains(&date_time.day)
+      || date_time.hour > 23 || date_time.minute > 59 || date_time.second > 60 {
+      return None;
+   }
+
+   // the local time is the UTC time plus the offset
+   let offset = match zone {
+      None                                         => None,
+      Some(zone) if zone.eq_ignore_ascii_case("z") => Some(0),
+      Some(zone)                                   => {
+         let offset = digits(&zone[1..], ':')?;
+         if offset.len() != 2 && offset.len() != 4 {
+            return None;
+         }
+
+         let secs = (number(&offset[..2]) * 3600 + number(&offset[2..]) * 60) as i64;
+         Some(if zone.starts_with('-') { -secs } else { secs })
+      }
+   };
+
+   Some(Timestamp {
+      seconds: date_time.seconds() - offset.unwrap_or(0),
+      nanos  : number(&fraction) * 10u32.pow(9 - fraction.len() as u32),
+      is_utc : offset.is_some()
+   })
+}
+
+/// A parsed file: the lines before the first record - like the `BEGIN:VCALENDAR` and
+/// the properties of a calendar - the records, and the lines after the last one.
+struct Document
+{
+   header : Vec<String>,
+   records: Vec<Record>,
+   footer : Vec<String>
+}
+
+fn parse(format: RecordFormat, text: &str) -> Document
+{
+   let mut doc = Document { header: Vec::new(), records: Vec::new(), footer: Vec::new() };
+   let mut record: Option<Record> = None;
+   let mut depth = 0;
+   for line in text.lines() {
+      // a folded line continues the property of the former line
+      let is_folded = line.starts_with(' ') || line.starts_with('\t');
+      let (name, value) = property(line);
+
+      if let Some(ref mut rec) = record {
+         rec.lines.push(line.to_string());
+         if is_folded {
+            if let Some(last) = rec.properties.last_mut() {
+               last.1.push_str(&line[1..]);
+            }
+         } else {
+            match name.as_str() {
+               "BEGIN" => depth += 1,
+               "END"   => depth -= 1,
+               _       => ()
+            }
+
+            rec.properties.push((name, value));
+         }
+      } else if name == "BEGIN" && format.records().contains(&value.to_uppercase().as_str()) {
+         record = Some(Record { name: value.to_uppercase(), properties: Vec::new(), lines: vec![line.to_string()] });
+         depth = 0;
+      } else if doc.records.is_empty() {
+         doc.header.push(line.to_string());
+      } else {
+         doc.footer.push(line.to_string());
+      }
+
+      if depth < 0 {
+         doc.records.extend(record.take());
+         depth = 0;
+      }
+   }
+
+   // an unterminated record at the end is kept as it is
+   doc.records.extend(record);
+   doc
+}
+
+// The name of the property of `line` in uppercase and its value, the parameters between
+// them - like in `DTSTART;TZID=Europe/Berlin:20150601T100000` - are dropped.
+fn property(line: &str) -> (String, String)
+{
+   let mut in_quotes = false;
+   let mut name_end = None;
+   for (i, c) in line.char_indices() {
+      match c {
+         '"'                  => in_quotes = ! in_quotes,
+         ';' if ! in_quotes   => { name_end.get_or_insert(i); }
+         ':' if ! in_quotes   => {
+            return (line[..name_end.unwrap_or(i)].trim().to_uppercase(), line[i + 1..].to_string());
+         }
+         _ => ()
+      }
+   }
+
+   (line.trim().to_uppercase(), String::new())
+}
+
+/// Merges the files `current` and `conflicting` of `format` record by record.
+pub fn merge(format: RecordFormat, current: &str, conflicting: &str, choose: &mut Choose) -> AppResult<Option<Merged>>
+{
+   let mut doc = parse(format, current);
+   let other = parse(format, conflicting);
+   let mut stats = Merged { text: String::new(), added: 0, conflicts: 0 };
+
+   for record in other.records {
+      let identity = record.identity();
+      let pos = match doc.records.iter().position(|r| r.identity() == identity) {
+         Some(pos) => pos,
+         None      => {
+            doc.records.push(record);
+            stats.added += 1;
+            continue;
+         }
+      };
+
+      if doc.records[pos].properties == record.properties {
+         continue;
+      }
+
+      stats.conflicts += 1;
+      let side = match (doc.records[pos].modified(format), record.modified(format)) {
+         (Some(ref cur), Some(ref conf)) if cur.is_utc == conf.is_utc && cur > conf => Side::Current,
+         (Some(ref cur), Some(ref conf)) if cur.is_utc == conf.is_utc && cur < conf => Side::Conflicting,
+         (Some(_), None) => Side::Current,
+         (None, Some(_)) => Side::Conflicting,
+         _ => {
+            let conflict = MergeConflict {
+               what       : doc.records[pos].describe(),
+               current    : doc.records[pos].show(),
+               conflicting: record.show()
+            };
+
+            match choose(&conflict)? {
+               Some(side) => side,
+               None       => return Ok(None)
+            }
+         }
+      };
+
+      if side == Side::Conflicting {
+         doc.records[pos] = record;
+      }
+   }
+
+   // the line endings of the current file are kept, RFC 5545 requires CRLF
+   let newline = if current.contains("\r\n") { "\r\n" } else { "\n" };
+   let mut lines = doc.header;
+   for record in doc.records {
+      lines.extend(record.lines);
+   }
+
+   lines.extend(doc.footer);
+   stats.text = lines.join(newline) + newline;
+   Ok(Some(stats))
+}
+
+#[test]
+fn tests()
+{
+   let calendar = |events: &str| format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n", events);
+   let event = |uid: &str, summary: &str, modified: &str| {
+      format!("BEGIN:VEVENT\r\nUID:{}\r\nSUMMARY:{}\r\nLAST-MODIFIED:{}\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\nEND:VALARM\r\nEND:VEVENT\r\n",
+              uid, summary, modified)
+   };
+
+   let mut asked = Vec::new();
+   let mut choose = |conflict: &MergeConflict| { asked.push(conflict.what.clone()); Ok(Some(Side::Current)) };
+
+   let current = calendar(&(event("1", "Dentist", "20150601T100000Z") + &event("2", "Lunch", "20150602T100000Z")));
+   let conflicting = calendar(&(event("2", "Lunch at noon", "20150603T100000Z") + &event("3", "Party", "20150601T100000Z")
+                                + &event("1", "Dentist", "20150601T100000Z")));
+
+   let merged = merge(RecordFormat::Calendar, &current, &conflicting, &mut choose).unwrap().unwrap();
+   assert_eq!(merged.text, calendar(&(event("1", "Dentist", "20150601T100000Z") + &event("2", "Lunch at noon", "20150603T100000Z")
+                                      + &event("3", "Party", "20150601T100000Z"))));
+   assert_eq!((merged.added, merged.conflicts), (1, 1));
+
+   // the same event changed at the same time in both files
+   let conflicting = calendar(&event("1", "Doctor", "20150601T100000Z"));
+   let merged = merge(RecordFormat::Calendar, &current, &conflicting, &mut choose).unwrap().unwrap();
+   assert_eq!(merged.text, current);
+   assert_eq!(asked, ["the event 'Dentist'"]);
+
+   let current = "BEGIN:VCARD\nUID:a\nFN:Dan\nREV:2015-06-01T10:00:00Z\nEND:VCARD\n";
+   let conflicting = "BEGIN:VCARD\nUID:a\nFN:Dan\nTEL:123\n 456\nREV:2015-06-02T10:00:00Z\nEND:VCARD\n\
+                      BEGIN:VCARD\nFN:Eve\nEND:VCARD\n";
+
+   let mut abort = |_: &MergeConflict| Ok(None);
+   let merged = merge(RecordFormat::Contacts, current, conflicting, &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, conflicting);
+
+   // the timestamps are compared in UTC
+   let vcard = |name: &str, rev: &str| format!("BEGIN:VCARD\nUID:a\nFN:{}\nREV:{}\nEND:VCARD\n", name, rev);
+   let merged = merge(RecordFormat::Contacts, &vcard("Dan", "2015-06-01T12:30:00+02:00"), &vcard("Dan B.", "20150601T110000Z"),
+                      &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, vcard("Dan B.", "20150601T110000Z"));
+
+   let merged = merge(RecordFormat::Contacts, &vcard("Dan", "2015-06-01T10:00:00.5Z"), &vcard("Dan B.", "2015-06-01T10:00:00Z"),
+                      &mut abort).unwrap().unwrap();
+   assert_eq!(merged.text, vcard("Dan", "2015-06-01T10:00:00.5Z"));
+
+   // a time without a UTC offset can't be compared with one in UTC
+   assert!(merge(RecordFormat::Contacts, &vcard("Dan", "20150601T100000"), &vcard("Dan B.", "20150602T100000Z"), &mut abort)
+      .unwrap().is_none());
+
+   assert_eq!(parse_timestamp("20150601T100000Z"), Some(Timestamp { seconds: 1433152800, nanos: 0, is_utc: true }));
+   assert_eq!(parse_timestamp("2015-06-01T05:00:00,25-05:00"), Some(Timestamp { seconds: 1433152800, nanos: 250_000_000, is_utc: true }));
+   assert_eq!(parse_timestamp("2015-06-01"), Some(Timestamp { seconds: 1433116800, nanos: 0, is_utc: false }));
+   assert_eq!(parse_timestamp("2015-13-01"), None);
+   assert_eq!(parse_timestamp("yesterday"), None);
+
+   assert_eq!(property("DTSTART;TZID=\"a:b\":20150601T100000"), ("DTSTART".to_string(), "20150601T100000".to_string()));
+   assert_eq!(format_of("vcf"), Some(RecordFormat::Contacts));
+}
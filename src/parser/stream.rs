@@ -8,7 +8,7 @@ pub struct Stream<'a>
 
 impl<'a> Stream<'a>
 {
-   pub fn new(input: &str) -> Stream
+   pub fn new(input: &str) -> Stream<'_>
    {
       Stream { 
          chars: input.chars(),
@@ -16,7 +16,7 @@ impl<'a> Stream<'a>
       }
    }
 
-   pub fn eof(&self) -> bool { self.chars.size_hint().0 <= 0 }
+   pub fn eof(&self) -> bool { self.chars.size_hint().0 == 0 }
 
    pub fn next_char_or_fail(&mut self) -> char
    {
@@ -80,12 +80,12 @@ fn tests()
    strm.next_char_or_fail();
    assert_eq!(strm.unconsumed(), "t".to_string());
 
-   assert_eq!(strm.eof(), false);
+   assert!(! strm.eof());
    strm.next_char_or_fail();
-   assert_eq!(strm.eof(), true);
+   assert!(strm.eof());
    assert_eq!(strm.unconsumed(), "".to_string());
 
    strm.pop_and_reset_pos();
-   assert_eq!(strm.eof(), false);
+   assert!(! strm.eof());
    assert_eq!(strm.unconsumed(), "ttt".to_string());
 }
@@ -0,0 +1,240 @@
+use std::io;
+use std::fs::{self, read_dir, create_dir_all};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use sha2::{Sha256, Digest};
+
+use app_result::{AppResult, AppError, WithPath};
+use file_system::{ScanWarning, escape_line, unescape_line};
+use appdirs;
+
+// A persistent index of the directory listings of a scanned hierarchy, so that
+// a later scan only has to read the directories, which changed in the meantime.
+//
+// The index of a start directory is the file `index/<SHA-256 of its canonical path>`
+// inside of the cache directory of confsolve. It has one block per directory:
+//
+//    `d\t<seconds>.<nanoseconds of the modification time>\t<path of the directory>`
+//    `f\t<name of a file inside of the directory>`
+//    `s\t<name of a subdirectory>`
+//
+// Adding, removing or renaming an entry changes the modification time of its
+// directory, so the cached listing of a directory is valid as long as the
+// modification time of the directory is unchanged.
+
+// Listings of directories modified this shortly before the scan aren't trusted,
+// because a change in the same tick of the file system clock wouldn't be noticed.
+const RACY_SECS: u64 = 2;
+
+/// The cached listing of one directory.
+#[derive(PartialEq, Debug)]
+struct Listing
+{
+   modified: (u64, u32),
+   files   : Vec<String>,
+   subdirs : Vec<String>
+}
+
+/// Returns all files of the directory hierarchy starting at `start_dir` and the parts
+/// of it which couldn't be scanned, like `walk_files`. Only the directories changed
+/// since the last scan of `start_dir` are read, and the index is updated afterwards.
+pub fn walk(start_dir: &Path) -> AppResult<(Vec<PathBuf>, Vec<ScanWarning>)>
+{
+   let index_file = index_file(start_dir)?;
+   let old_index = read_index(&index_file);
+   let mut new_index = HashMap::new();
+
+   let now = modified_secs(SystemTime::now());
+   let mut files = Vec::new();
+   let mut warnings = Vec::new();
+   let mut dirs = vec![start_dir.to_path_buf()];
+   while let Some(dir) = dirs.pop() {
+      let modified = match fs::metadata(&dir).and_then(|m| m.modified()) {
+         Ok(modified) => modified_secs(modified),
+         Err(err)     => {
+            if dir == start_dir {
+               return Err(AppError::io(err, &dir));
+            }
+
+            warnings.push(ScanWarning::new(&dir, &err));
+            continue;
+         }
+      };
+
+      let cached = old_index.get(&dir)
+         .filter(|listing| listing.modified == modified && modified.0 + RACY_SECS < now.0);
+
+      let listing = match cached {
+         Some(listing) => Listing { modified, files: listing.files.clone(), subdirs: listing.subdirs.clone() },
+         None          => {
+            match read_listing(&dir, modified, &mut warnings) {
+               Ok(listing) => listing,
+               Err(err)    => {
+                  if dir == start_dir {
+                     return Err(AppError::io(err, &dir));
+                  }
+
+                  warnings.push(ScanWarning::new(&dir, &err));
+                  continue;
+               }
+            }
+         }
+      };
+
+      files.extend(listing.files.iter().map(|f| dir.join(f)));
+      dirs.extend(listing.subdirs.iter().map(|d| dir.join(d)));
+      new_index.insert(dir, listing);
+   }
+
+   write_index(&index_file, &new_index)?;
+   Ok((files, warnings))
+}
+
+// Reads the listing of `dir`. Entries which couldn't be read are recorded as warnings,
+// and the listing then gets an invalid modification time, so that it's read again.
+fn read_listing(dir: &Path, modified: (u64, u32), warnings: &mut Vec<ScanWarning>) -> io::Result<Listing>
+{
+   let mut listing = Listing { modified, files: Vec::new(), subdirs: Vec::new() };
+   for entry in read_dir(dir)? {
+      let entry = match entry {
+         Ok(entry) => entry,
+         Err(err)  => {
+            warnings.push(ScanWarning::new(dir, &err));
+            listing.modified = (0, 0);
+            continue;
+         }
+      };
+
+      let path = entry.path();
+      let name = match entry.file_name().into_string() {
+         Ok(name) => name,
+         Err(..)  => {
+            // a name which isn't valid unicode can't be cached
+            listing.modified = (0, 0);
+            continue;
+         }
+      };
+
+      if path.is_file() {
+         listing.files.push(name);
+      }
+      else if path.is_dir() {
+         listing.subdirs.push(name);
+      }
+   }
+
+   Ok(listing)
+}
+
+fn index_file(start_dir: &Path) -> AppResult<PathBuf>
+{
+   let start_dir = fs::canonicalize(start_dir).with_path(start_dir)?;
+   let mut hasher = Sha256::new();
+   hasher.update(start_dir.to_string_lossy().as_bytes());
+   let hash = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+   appdirs::cache("confsolve")
+      .map(|dir| dir.join("index").join(hash))
+      .ok_or(AppError::from_string("Couldn't get cache directory!".to_string()))
+}
+
+// An unreadable or invalid index is treated like an empty one.
+fn read_index(index_file: &Path) -> HashMap<PathBuf, Listing>
+{
+   fs::read_to_string(index_file).ok()
+      .and_then(|index| parse_index(&index))
+      .unwrap_or_default()
+}
+
+fn parse_index(index: &str) -> Option<HashMap<PathBuf, Listing>>
+{
+   let mut listings = HashMap::new();
+   let mut current: Option<(PathBuf, Listing)> = None;
+   for line in index.lines() {
+      let (kind, rest) = line.split_once('\t')?;
+      match kind {
+         "d" => {
+            if let Some((dir, listing)) = current.take() {
+               listings.insert(dir, listing);
+            }
+
+            let mut fields = rest.splitn(2, '\t');
+            let mut time = fields.next()?.splitn(2, '.');
+            let modified = (time.next()?.parse::<u64>().ok()?, time.next()?.parse::<u32>().ok()?);
+            let dir = PathBuf::from(unescape_line(fields.next()?));
+            current = Some((dir, Listing { modified, files: Vec::new(), subdirs: Vec::new() }));
+         }
+
+         "f" => current.as_mut()?.1.files.push(unescape_line(rest)),
+         "s" => current.as_mut()?.1.subdirs.push(unescape_line(rest)),
+         _   => return None
+      }
+   }
+
+   if let Some((dir, listing)) = current {
+      listings.insert(dir, listing);
+   }
+
+   Some(listings)
+}
+
+fn format_index(listings: &HashMap<PathBuf, Listing>) -> String
+{
+   let mut index = String::new();
+   for (dir, listing) in listings.iter() {
+      let dir = match dir.to_str() {
+         Some(dir) => dir,
+         None      => continue
+      };
+
+      index.push_str(&format!("d\t{}.{:09}\t{}\n", listing.modified.0, listing.modified.1, escape_line(dir)));
+      for file in listing.files.iter() {
+         index.push_str(&format!("f\t{}\n", escape_line(file)));
+      }
+
+      for subdir in listing.subdirs.iter() {
+         index.push_str(&format!("s\t{}\n", escape_line(subdir)));
+      }
+   }
+
+   index
+}
+
+fn write_index(index_file: &Path, listings: &HashMap<PathBuf, Listing>) -> AppResult<()>
+{
+   if let Some(dir) = index_file.parent() {
+      create_dir_all(dir).with_path(dir)?;
+   }
+
+   // written under a temporary name first, so that there's never an incomplete index
+   let tmp_file = index_file.with_extension("tmp");
+   fs::write(&tmp_file, format_index(listings)).with_path(&tmp_file)?;
+   fs::rename(&tmp_file, index_file).with_path(index_file)?;
+   Ok(())
+}
+
+fn modified_secs(time: SystemTime) -> (u64, u32)
+{
+   let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+   (since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+#[test]
+fn tests()
+{
+   let mut listings = HashMap::new();
+   listings.insert(PathBuf::from("/a/b\nc"), Listing {
+      modified: (1433168585, 5),
+      files   : vec!["x (blub's conflicted copy 2011-04-30).txt".to_string(), "x.txt".to_string()],
+      subdirs : vec!["d\te".to_string()]
+   });
+
+   listings.insert(PathBuf::from("/a/b\nc/d\te"), Listing { modified: (1, 0), files: Vec::new(), subdirs: Vec::new() });
+
+   let index = format_index(&listings);
+   assert!(index.contains("d\t1433168585.000000005\t/a/b\\nc\n"));
+   assert_eq!(parse_index(&index), Some(listings));
+   assert_eq!(parse_index("x\tblub"), None);
+   assert_eq!(parse_index("f\tx.txt"), None);
+}
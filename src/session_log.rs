@@ -0,0 +1,214 @@
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File, OpenOptions, create_dir_all};
+
+use app_result::{AppResult, AppError, WithPath};
+use date_time::DateTime;
+use appdirs;
+
+/// The name of the human readable log file.
+pub const TEXT_LOG: &str = "session.log";
+
+/// The name of the log file with one JSON object per line.
+pub const JSON_LOG: &str = "session.jsonl";
+
+/// An append-only log of every conflict shown, every reply of the user
+/// and every resulting file system operation.
+///
+/// Every event is written into two files inside of the log directory:
+/// `session.log` with one tab separated line per event:
+///
+///    `<date time>\t<user>@<host>\t<event>\t<path>\t<details>`
+///
+/// and `session.jsonl` with one JSON object per event:
+///
+///    `{"time":"..","user":"..","host":"..","event":"..","path":"..","details":".."}`
+///
+pub struct SessionLog
+{
+   // the text and the JSON log file, none if logging is disabled
+   files: Option<(File, File)>,
+   user : String,
+   host : String
+}
+
+impl SessionLog
+{
+   /// Opens the log files inside of `log_dir` for appending.
+   pub fn open(log_dir: &Path) -> AppResult<SessionLog>
+   {
+      if ! log_dir.is_dir() {
+         create_dir_all(log_dir).with_path(log_dir)?;
+      }
+
+      let open = |name| {
+         let file = log_dir.join(name);
+         OpenOptions::new().create(true).append(true).open(&file).with_path(&file)
+      };
+
+      Ok(SessionLog {
+         files: Some((open(TEXT_LOG)?, open(JSON_LOG)?)),
+         user : user_name(),
+         host : host_name()
+      })
+   }
+
+   /// A log which ignores all events.
+   pub fn disabled() -> SessionLog
+   {
+      SessionLog { files: None, user: String::new(), host: String::new() }
+   }
+
+   /// Appends the event `event` concerning `path` with additional `details` to the log.
+   pub fn log(&mut self, event: &str, path: &Path, details: &str) -> AppResult<()>
+   {
+      let (ref mut text, ref mut json) = match self.files {
+         Some(ref mut files) => files,
+         None                => return Ok(())
+      };
+
+      let time = DateTime::now().to_string();
+      let path = absolute_path(path);
+      let path = path.to_string_lossy();
+
+      writeln!(text, "{}\t{}@{}\t{}\t{}\t{}",
+               time, self.user, self.host, event, escape_text(&path), escape_text(details))?;
+
+      writeln!(json, "{{\"time\":{},\"user\":{},\"host\":{},\"event\":{},\"path\":{},\"details\":{}}}",
+               json_string(&time), json_string(&self.user), json_string(&self.host),
+               json_string(event), json_string(&path), json_string(details))?;
+
+      Ok(())
+   }
+}
+
+/// One event read from the text log.
+pub struct LogEntry
+{
+   pub time   : String,
+   pub who    : String,
+   pub event  : String,
+   pub path   : String,
+   pub details: String
+}
+
+/// Reads the events of the text log inside of `log_dir`, whose path contains
+/// `path` and whose date and time starts with `date`, e.g. `2015-06` or `2015-06-01`.
+pub fn query(log_dir: &Path, path: Option<&str>, date: Option<&str>) -> AppResult<Vec<LogEntry>>
+{
+   let log_file = log_dir.join(TEXT_LOG);
+   if ! log_file.is_file() {
+      return Ok(Vec::new());
+   }
+
+   let mut entries = Vec::new();
+   for line in fs::read_to_string(&log_file).with_path(&log_file)?.lines() {
+      let fields = line.splitn(5, '\t').collect::<Vec<&str>>();
+      if fields.len() != 5 {
+         continue;
+      }
+
+      let entry = LogEntry {
+         time   : fields[0].to_string(),
+         who    : fields[1].to_string(),
+         event  : fields[2].to_string(),
+         path   : fields[3].to_string(),
+         details: fields[4].to_string()
+      };
+
+      if path.map(|p| entry.path.contains(p)).unwrap_or(true)
+         && date.map(|d| entry.time.starts_with(d)).unwrap_or(true) {
+         entries.push(entry);
+      }
+   }
+
+   Ok(entries)
+}
+
+/// Returns the log directory of confsolve, which is the directory specified by the
+/// environment variable `CONFSOLVE_LOG_DIR` or the directory `log` inside of the
+/// data directory of confsolve.
+pub fn log_dir() -> AppResult<PathBuf>
+{
+   if let Some(dir) = env::var_os("CONFSOLVE_LOG_DIR") {
+      return Ok(PathBuf::from(dir));
+   }
+
+   appdirs::data_home()
+      .map(|dir| dir.join("confsolve").join("log"))
+      .ok_or(AppError::from_string("Couldn't get data directory!".to_string()))
+}
+
+fn absolute_path(path: &Path) -> PathBuf
+{
+   if path.is_absolute() || path.as_os_str().is_empty() {
+      return path.to_path_buf();
+   }
+
+   env::current_dir().map(|dir| dir.join(path)).unwrap_or(path.to_path_buf())
+}
+
+/// The name of the user running confsolve.
+pub fn user_name() -> String
+{
+   ["USER", "USERNAME", "LOGNAME"].iter()
+      .filter_map(|var| env::var(var).ok())
+      .find(|name| ! name.is_empty())
+      .unwrap_or("unknown".to_string())
+}
+
+/// The name of the host confsolve is running on.
+#[cfg(unix)]
+pub fn host_name() -> String
+{
+   use libc;
+
+   let mut buf = [0u8; 256];
+   let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+   let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+   if result != 0 || len == 0 {
+      return "unknown".to_string();
+   }
+
+   String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn host_name() -> String
+{
+   env::var("COMPUTERNAME").unwrap_or("unknown".to_string())
+}
+
+// Tabs and line endings would break the line format of the text log.
+fn escape_text(text: &str) -> String
+{
+   text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn json_string(text: &str) -> String
+{
+   let mut json = String::with_capacity(text.len() + 2);
+   json.push('"');
+   for c in text.chars() {
+      match c {
+         '"'  => json.push_str("\\\""),
+         '\\' => json.push_str("\\\\"),
+         '\n' => json.push_str("\\n"),
+         '\r' => json.push_str("\\r"),
+         '\t' => json.push_str("\\t"),
+         c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+         c => json.push(c)
+      }
+   }
+
+   json.push('"');
+   json
+}
+
+#[test]
+fn tests()
+{
+   assert_eq!(json_string("a \"b\"\\c\n\u{1}"), "\"a \\\"b\\\"\\\\c\\n\\u0001\"");
+   assert_eq!(escape_text("a\tb\nc"), "a\\tb\\nc");
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::path::Path;
+use std::fs::File;
+use std::fmt::{Display, Formatter, Error};
+
+use app_result::{AppResult, WithPath};
+use date_time::DateTime;
+use trash::format_size;
+
+/// What happened to the conflicts of one run of confsolve.
+#[derive(Default)]
+pub struct Summary
+{
+   /// the start directory of the run
+   pub start_dir: String,
+
+   /// conflicts found by the scan
+   pub found: usize,
+
+   /// conflicts resolved by taking one of the conflicting files
+   pub taken: usize,
+
+   /// conflicts resolved by moving all conflicting files to the trash
+   pub trashed: usize,
+
+   /// conflicts resolved by the built-in merge of the original and a conflicting file
+   pub merged: usize,
+
+   /// conflicts skipped by the user
+   pub skipped: usize,
+
+   /// conflicts whose original file is missing
+   pub orphaned: usize,
+
+   /// conflicts whose resolution failed
+   pub errored: usize,
+
+   /// conflicts not shown, because the user quit before
+   pub left: usize,
+
+   /// the number of bytes moved to the trash
+   pub trashed_bytes: u64,
+
+   /// where the trashed files are put into
+   pub trash: String
+}
+
+impl Summary
+{
+   /// Writes the summary as a report into `file`, as HTML if the
+   /// extension of `file` is `html` or `htm`, otherwise as Markdown.
+   pub fn write_report(&self, file: &Path) -> AppResult<()>
+   {
+      let is_html = file.extension()
+         .and_then(|e| e.to_str())
+         .map(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"))
+         .unwrap_or(false);
+
+      let report = if is_html { self.to_html() } else { self.to_markdown() };
+      File::create(file).with_path(file)?.write_all(report.as_bytes()).with_path(file)?;
+      Ok(())
+   }
+
+   fn rows(&self) -> Vec<(&'static str, String)>
+   {
+      vec![
+         ("Conflicts found"     , self.found.to_string()),
+         ("Resolved by take"    , self.taken.to_string()),
+         ("Moved to trash"      , self.trashed.to_string()),
+         ("Resolved by merge"   , self.merged.to_string()),
+         ("Skipped"             , self.skipped.to_string()),
+         ("Orphaned"            , self.orphaned.to_string()),
+         ("Errored"             , self.errored.to_string()),
+         ("Left after quit"     , self.left.to_string()),
+         ("Bytes moved to trash", format_size(self.trashed_bytes)),
+         ("Trash"               , self.trash.clone())
+      ]
+   }
+
+   fn to_markdown(&self) -> String
+   {
+      let mut md = format!("# confsolve report\n\n`{}` on {}\n\n| | |\n|---|---|\n",
+                           self.start_dir, DateTime::now());
+
+      for (name, value) in self.rows() {
+         md.push_str(&format!("| {} | {} |\n", name, value.replace('|', "\\|")));
+      }
+
+      md
+   }
+
+   fn to_html(&self) -> String
+   {
+      let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>confsolve report</title></head>\n\
+                              <body>\n<h1>confsolve report</h1>\n<p><code>{}</code> on {}</p>\n<table>\n",
+                             escape_html(&self.start_dir), DateTime::now());
+
+      for (name, value) in self.rows() {
+         html.push_str(&format!("<tr><th align=\"left\">{}</th><td>{}</td></tr>\n", name, escape_html(&value)));
+      }
+
+      html.push_str("</table>\n</body>\n</html>\n");
+      html
+   }
+}
+
+impl Display for Summary
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      writeln!(f, "Summary:")?;
+      for (name, value) in self.rows() {
+         writeln!(f, "   {:<21}: {}", name, value)?;
+      }
+
+      Ok(())
+   }
+}
+
+fn escape_html(text: &str) -> String
+{
+   text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[test]
+fn tests()
+{
+   let summary = Summary { found: 3, taken: 1, trashed_bytes: 2048, trash: "a|b".to_string(), ..Summary::default() };
+   let md = summary.to_markdown();
+   assert!(md.contains("| Conflicts found | 3 |"));
+   assert!(md.contains("| Bytes moved to trash | 2.0 KiB |"));
+   assert!(md.contains("| Trash | a\\|b |"));
+
+   let summary = Summary { trash: "<dir>".to_string(), ..Summary::default() };
+   assert!(summary.to_html().contains("<td>&lt;dir&gt;</td>"));
+   assert!(format!("{}", summary).contains("Conflicts found      : 0"));
+}
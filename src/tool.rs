@@ -0,0 +1,258 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::fmt::{Display, Formatter, Error};
+
+use app_result::{AppResult, AppError};
+
+// The diff and merge commands are split into words like by a shell: words are
+// separated by whitespace, single quotes keep everything literally, inside of
+// double quotes and outside of quotes a backslash escapes the next character.
+//
+// The words might contain the placeholders:
+//
+//    `{left}`, `{right}`   the two files
+//    `{base}`              the original file of the conflict
+//    `{left_label}`,
+//    `{right_label}`       descriptions of the two files, e.g. for `meld --label`
+//
+// If neither `{left}` nor `{right}` is used, the two files are appended.
+//
+// The command line comparing all files of a conflict at once might contain
+// the placeholder `{files}`, otherwise all files are appended.
+
+/// The kind of a command line called with two files.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToolKind
+{
+   Diff,
+   DiffAll,
+   Merge
+}
+
+impl Display for ToolKind
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         ToolKind::Diff    => write!(f, "diff"),
+         ToolKind::DiffAll => write!(f, "multi-way diff"),
+         ToolKind::Merge   => write!(f, "merge")
+      }
+   }
+}
+
+/// The files and their descriptions, which replace the placeholders of a diff or merge command.
+pub struct ToolFiles<'a>
+{
+   pub left       : &'a Path,
+   pub right      : &'a Path,
+   pub base       : &'a Path,
+   pub left_label : String,
+   pub right_label: String
+}
+
+/// Builds the command of the diff or merge command line `tool` for `files`.
+pub fn command(tool: &str, files: &ToolFiles) -> AppResult<Command>
+{
+   let words = split_words(tool)
+      .map_err(|err| AppError::InvalidUsage(format!("Invalid command '{}': {}!", tool, err)))?;
+
+   let has_files = words.iter().any(|w| w.contains("{left}") || w.contains("{right}"));
+   let mut cmd = Command::new(&words[0]);
+   for word in words.iter().skip(1) {
+      match word.as_str() {
+         // a whole path is passed unchanged, also if it isn't valid unicode
+         "{left}"  => cmd.arg(files.left),
+         "{right}" => cmd.arg(files.right),
+         "{base}"  => cmd.arg(files.base),
+         _         => cmd.arg(expand(word, files))
+      };
+   }
+
+   if ! has_files {
+      cmd.arg(files.left);
+      cmd.arg(files.right);
+   }
+
+   Ok(cmd)
+}
+
+/// Builds the command of the command line `tool` comparing all of `files` at once.
+pub fn command_all(tool: &str, files: &[&Path]) -> AppResult<Command>
+{
+   let words = split_words(tool)
+      .map_err(|err| AppError::InvalidUsage(format!("Invalid command '{}': {}!", tool, err)))?;
+
+   let mut cmd = Command::new(&words[0]);
+   for word in words.iter().skip(1) {
+      if word == "{files}" {
+         cmd.args(files);
+      } else {
+         cmd.arg(word);
+      }
+   }
+
+   if ! words.iter().any(|w| w == "{files}") {
+      cmd.args(files);
+   }
+
+   Ok(cmd)
+}
+
+/// Runs the command `cmd` of a tool of `kind` and waits until it's finished.
+/// The tool inherits the terminal, so the output of terminal tools like `diff -u` is shown
+/// and they can be interactive. A tool which couldn't be started or failed is reported
+/// as error, with a hint for a missing tool.
+pub fn run(kind: ToolKind, mut cmd: Command) -> AppResult<()>
+{
+   let program = cmd.get_program().to_string_lossy().into_owned();
+   let status = cmd.status().map_err(|err| {
+      if err.kind() == io::ErrorKind::NotFound {
+         AppError::from_string(format!("The {} tool '{}' wasn't found, install it or set another one, {}!",
+                                       kind, program, fallback_hint(kind)))
+      } else {
+         AppError::from_string(format!("Couldn't run the {} tool '{}': {}!", kind, program, err))
+      }
+   })?;
+
+   match status.code() {
+      Some(0) => Ok(()),
+
+      // like `diff` and `cmp`, many diff tools exit with 1 if the files differ
+      Some(1) if kind != ToolKind::Merge => Ok(()),
+
+      Some(code) => Err(AppError::from_string(format!("The {} tool '{}' failed with exit status {}!", kind, program, code))),
+      None       => Err(AppError::from_string(format!("The {} tool '{}' has been terminated by a signal!", kind, program)))
+   }
+}
+
+fn fallback_hint(kind: ToolKind) -> &'static str
+{
+   match kind {
+      ToolKind::Diff    => "e.g. by 'CONFSOLVE_DIFF=\"diff -u\"' or 'diff' in the configuration",
+      ToolKind::DiffAll => "e.g. by 'diff_all = \"vimdiff\"' in the configuration",
+      ToolKind::Merge   => "e.g. by 'merge = \"meld\"' in the configuration"
+   }
+}
+
+/// Checks that the diff or merge command line `tool` can be split into words.
+pub fn validate(tool: &str) -> Result<(), String>
+{
+   split_words(tool).map(|_| ())
+}
+
+fn expand(word: &str, files: &ToolFiles) -> String
+{
+   word.replace("{left_label}", &files.left_label)
+      .replace("{right_label}", &files.right_label)
+      .replace("{left}", &files.left.to_string_lossy())
+      .replace("{right}", &files.right.to_string_lossy())
+      .replace("{base}", &files.base.to_string_lossy())
+}
+
+/// Splits `input` into words like a shell, see above.
+fn split_words(input: &str) -> Result<Vec<String>, String>
+{
+   let mut words = Vec::new();
+   let mut word: Option<String> = None;
+   let mut chars = input.chars();
+   while let Some(c) = chars.next() {
+      match c {
+         c if c.is_whitespace() => {
+            if let Some(word) = word.take() {
+               words.push(word);
+            }
+         }
+
+         '\'' => {
+            let word = word.get_or_insert_with(String::new);
+            loop {
+               match chars.next() {
+                  Some('\'') => break,
+                  Some(c)    => word.push(c),
+                  None       => return Err("missing closing single quote".to_string())
+               }
+            }
+         }
+
+         '"' => {
+            let word = word.get_or_insert_with(String::new);
+            loop {
+               match chars.next() {
+                  Some('"')  => break,
+                  Some('\\') => {
+                     match chars.next() {
+                        Some(c) if "\"\\$`".contains(c) => word.push(c),
+                        Some(c) => { word.push('\\'); word.push(c); }
+                        None    => return Err("missing closing double quote".to_string())
+                     }
+                  }
+
+                  Some(c) => word.push(c),
+                  None    => return Err("missing closing double quote".to_string())
+               }
+            }
+         }
+
+         '\\' => {
+            match chars.next() {
+               Some(c) => word.get_or_insert_with(String::new).push(c),
+               None    => return Err("backslash at the end".to_string())
+            }
+         }
+
+         c => word.get_or_insert_with(String::new).push(c)
+      }
+   }
+
+   if let Some(word) = word {
+      words.push(word);
+   }
+
+   if words.is_empty() {
+      return Err("no command given".to_string());
+   }
+
+   Ok(words)
+}
+
+#[test]
+fn tests()
+{
+   let words = |input: &str| split_words(input).unwrap();
+   assert_eq!(words("gvimdiff  -f"), ["gvimdiff", "-f"]);
+   assert_eq!(words("'/opt/my tools/diff' \"a \\\"b\\\"\" c\\ d ''"), ["/opt/my tools/diff", "a \"b\"", "c d", ""]);
+   assert_eq!(words("meld --label={left_label}"), ["meld", "--label={left_label}"]);
+   assert!(split_words("meld 'a").is_err());
+   assert!(split_words("meld \"a").is_err());
+   assert!(split_words("  ").is_err());
+
+   let files = ToolFiles {
+      left       : Path::new("/a/x.txt"),
+      right      : Path::new("/a/x (conflicted copy).txt"),
+      base       : Path::new("/a/x.txt"),
+      left_label : "x.txt".to_string(),
+      right_label: "(1) from blub".to_string()
+   };
+
+   let args = |tool: &str| {
+      let cmd = command(tool, &files).unwrap();
+      cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect::<Vec<String>>()
+   };
+
+   assert_eq!(args("gvimdiff -f"), ["-f", "/a/x.txt", "/a/x (conflicted copy).txt"]);
+   assert_eq!(args("meld --label {left_label} --label={right_label} {left} {right}"),
+              ["--label", "x.txt", "--label=(1) from blub", "/a/x.txt", "/a/x (conflicted copy).txt"]);
+   assert_eq!(args("code --diff --wait {right} {left}"), ["--diff", "--wait", "/a/x (conflicted copy).txt", "/a/x.txt"]);
+
+   assert!(run(ToolKind::Diff, command("false", &files).unwrap()).is_ok());
+   assert!(run(ToolKind::Merge, command("false", &files).unwrap()).is_err());
+   assert!(run(ToolKind::Diff, command("confsolve-missing-diff-tool", &files).unwrap()).is_err());
+
+   let paths = [Path::new("a"), Path::new("b"), Path::new("c")];
+   let cmd = command_all("meld {files} --newtab", &paths).unwrap();
+   assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["a", "b", "c", "--newtab"]);
+   let cmd = command_all("vimdiff", &paths).unwrap();
+   assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["a", "b", "c"]);
+}
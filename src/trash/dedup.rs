@@ -0,0 +1,256 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions, create_dir_all, remove_file, copy};
+
+use app_result::{AppResult, AppError, WithPath};
+use file_system::{walk_files, hash_file, escape_line, unescape_line, random_suffix};
+
+/// The name of the manifest inside of a session directory.
+pub const MANIFEST: &str = ".manifest";
+
+/// The name of the directory inside of the trash directory holding the blobs.
+pub const BLOBS: &str = ".blobs";
+
+// The name of the lock file inside of the directory of the blobs.
+const BLOBS_LOCK: &str = ".lock";
+
+// The deduplicated storage of the private trash.
+//
+// The contents of the trashed files are stored only once as blobs named by
+// their SHA-256 hash:
+//
+//    `<trash_dir>/.blobs/<first two chars of hash>/<hash>`
+//
+// The manifest of a session maps the trashed files to their blobs, one line
+// per file in the form:
+//
+//    `<hash>\t<size>\t<path relative to the session directory>`
+//
+// Storing a blob and collecting the garbage - possibly by concurrent runs of
+// confsolve - hold the lock file `.blobs/.lock`, so that a blob which isn't
+// referenced by a manifest yet is never removed.
+
+/// One trashed file recorded in the manifest of a session.
+#[derive(PartialEq, Debug)]
+pub struct ManifestEntry
+{
+   pub hash: String,
+   pub size: u64,
+   pub path: PathBuf
+}
+
+/// Stores the contents of `file` as a blob of the trash directory `trash_dir`,
+/// records it as `rel_path` in the manifest of `session_dir` and removes `file`.
+/// If `rel_path` is already recorded, a suffix is added to it until it's unique.
+/// Returns the path of the blob.
+pub fn store(trash_dir: &Path, session_dir: &Path, file: &Path, rel_path: &Path) -> AppResult<PathBuf>
+{
+   let hash = hash_file(file)?;
+   let size = fs::metadata(file).with_path(file)?.len();
+   let blob = blob_path(trash_dir, &hash);
+   let _lock = BlobsLock::acquire(trash_dir)?;
+   if ! blob.is_file() {
+      if let Some(parent) = blob.parent() {
+         create_dir_all(parent).with_path(parent)?;
+      }
+
+      // copied under a temporary name first, so that there's never an incomplete blob,
+      // which is unique, so that concurrent runs storing the same blob don't clobber each other
+      let tmp_blob = blob.with_extension(format!("{}.tmp", random_suffix()));
+      copy(file, &tmp_blob).with_path(&tmp_blob)?;
+      fs::rename(&tmp_blob, &blob).with_path(&blob)?;
+   }
+
+   let path = unique_path(&read_manifest(session_dir)?, rel_path)?;
+   append_to_manifest(session_dir, &ManifestEntry { hash, size, path })?;
+   remove_file(file).with_path(file)?;
+   Ok(blob)
+}
+
+/// Copies the contents of the trashed file `entry` to `to_file`.
+pub fn restore(trash_dir: &Path, entry: &ManifestEntry, to_file: &Path) -> AppResult<()>
+{
+   copy(blob_path(trash_dir, &entry.hash), to_file).with_path(to_file)?;
+   Ok(())
+}
+
+/// Reads the manifest of `session_dir`, which is empty
+/// if the session doesn't have a manifest.
+pub fn read_manifest(session_dir: &Path) -> AppResult<Vec<ManifestEntry>>
+{
+   let manifest = session_dir.join(MANIFEST);
+   if ! manifest.is_file() {
+      return Ok(Vec::new());
+   }
+
+   let mut entries = Vec::new();
+   for line in fs::read_to_string(&manifest).with_path(&manifest)?.lines() {
+      let entry = parse_manifest_line(line)
+         .ok_or(AppError::from_string(format!("Invalid line in manifest '{}': '{}'!", manifest.display(), line)))?;
+
+      entries.push(entry);
+   }
+
+   Ok(entries)
+}
+
+/// Removes the blobs of `trash_dir`, which aren't referenced by the manifest
+/// of any session, and returns the number of bytes freed.
+pub fn collect_garbage(trash_dir: &Path) -> AppResult<u64>
+{
+   let blobs_dir = trash_dir.join(BLOBS);
+   if ! blobs_dir.is_dir() {
+      return Ok(0);
+   }
+
+   let _lock = BlobsLock::acquire(trash_dir)?;
+   let mut referenced = HashSet::new();
+   for entry in fs::read_dir(trash_dir).with_path(trash_dir)? {
+      let path = entry.with_path(trash_dir)?.path();
+      if path.is_dir() && path != blobs_dir {
+         for manifest_entry in read_manifest(&path)? {
+            referenced.insert(manifest_entry.hash);
+         }
+      }
+   }
+
+   // temporary blobs left by an interrupted run are removed too
+   let mut freed = 0;
+   for blob in walk_files(&blobs_dir)? {
+      let is_referenced = blob.file_name()
+         .and_then(|n| n.to_str())
+         .map(|n| n == BLOBS_LOCK || referenced.contains(n))
+         .unwrap_or(false);
+
+      if ! is_referenced {
+         freed += blob.metadata().map(|m| m.len()).unwrap_or(0);
+         remove_file(&blob).with_path(&blob)?;
+      }
+   }
+
+   Ok(freed)
+}
+
+/// The number of bytes used by the blobs of `trash_dir`.
+pub fn blobs_size(trash_dir: &Path) -> AppResult<u64>
+{
+   let blobs_dir = trash_dir.join(BLOBS);
+   if ! blobs_dir.is_dir() {
+      return Ok(0);
+   }
+
+   Ok(walk_files(&blobs_dir)?.fold(0, |size, blob| size + blob.metadata().map(|m| m.len()).unwrap_or(0)))
+}
+
+fn blob_path(trash_dir: &Path, hash: &str) -> PathBuf
+{
+   trash_dir.join(BLOBS).join(&hash[..2]).join(hash)
+}
+
+// Returns `rel_path`, or if it's already recorded in `entries`
+// - like `unique_file` - the first unique one with a suffix.
+fn unique_path(entries: &[ManifestEntry], rel_path: &Path) -> AppResult<PathBuf>
+{
+   let is_unique = |path: &Path| entries.iter().all(|e| e.path != path);
+   if is_unique(rel_path) {
+      return Ok(rel_path.to_path_buf());
+   }
+
+   let filename_str = rel_path.file_name()
+      .and_then(|f| f.to_str())
+      .ok_or(AppError::from_string(format!("Couldn't get filename_str of '{}'!", rel_path.display())))?;
+
+   let mut path = rel_path.to_path_buf();
+   for i in 2..10000 {
+      path.set_file_name(format!("{}-{}", filename_str, i));
+      if is_unique(&path) {
+         return Ok(path);
+      }
+   }
+
+   Err(AppError::from_string(format!("Couldn't get a unique path for '{}'!", rel_path.display())))
+}
+
+// The exclusive lock of the blobs of a trash directory, released when it's dropped.
+struct BlobsLock
+{
+   _file: File
+}
+
+impl BlobsLock
+{
+   // Waits until the lock of the blobs of `trash_dir` is acquired.
+   fn acquire(trash_dir: &Path) -> AppResult<BlobsLock>
+   {
+      let blobs_dir = trash_dir.join(BLOBS);
+      create_dir_all(&blobs_dir).with_path(&blobs_dir)?;
+
+      let lock_file = blobs_dir.join(BLOBS_LOCK);
+      let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_file).with_path(&lock_file)?;
+      lock_exclusive(&file).with_path(&lock_file)?;
+      Ok(BlobsLock { _file: file })
+   }
+}
+
+#[cfg(unix)]
+fn lock_exclusive(file: &File) -> io::Result<()>
+{
+   use std::os::unix::io::AsRawFd;
+   use libc;
+
+   if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+      Ok(())
+   } else {
+      Err(io::Error::last_os_error())
+   }
+}
+
+// Without a way to lock a file, concurrent runs aren't prevented.
+#[cfg(not(unix))]
+fn lock_exclusive(_file: &File) -> io::Result<()>
+{
+   Ok(())
+}
+
+fn append_to_manifest(session_dir: &Path, entry: &ManifestEntry) -> AppResult<()>
+{
+   let path_str = entry.path.to_str()
+      .ok_or(AppError::from_string(format!("Couldn't get path_str of '{}'!", entry.path.display())))?;
+
+   let manifest_file = session_dir.join(MANIFEST);
+   create_dir_all(session_dir).with_path(session_dir)?;
+   let mut manifest = OpenOptions::new().create(true).append(true).open(&manifest_file).with_path(&manifest_file)?;
+   writeln!(manifest, "{}\t{}\t{}", entry.hash, entry.size, escape_line(path_str)).with_path(&manifest_file)?;
+   Ok(())
+}
+
+fn parse_manifest_line(line: &str) -> Option<ManifestEntry>
+{
+   let mut fields = line.splitn(3, '\t');
+   let hash = fields.next()?;
+   let size = fields.next()?.parse::<u64>().ok()?;
+   let path = unescape_line(fields.next()?);
+   if hash.len() < 2 || ! hash.chars().all(|c| c.is_ascii_hexdigit()) || path.is_empty() {
+      return None;
+   }
+
+   Some(ManifestEntry { hash: hash.to_string(), size, path: PathBuf::from(path) })
+}
+
+#[test]
+fn tests()
+{
+   assert_eq!(parse_manifest_line("ab12\t10\ta/b c.txt"),
+              Some(ManifestEntry { hash: "ab12".to_string(), size: 10, path: PathBuf::from("a/b c.txt") }));
+   assert_eq!(parse_manifest_line("ab12\t10\ta\tb"),
+              Some(ManifestEntry { hash: "ab12".to_string(), size: 10, path: PathBuf::from("a\tb") }));
+   assert_eq!(parse_manifest_line("ab12\tx\ta"), None);
+   assert_eq!(parse_manifest_line("xy12\t10\ta"), None);
+   assert_eq!(parse_manifest_line("ab12\t10"), None);
+
+   let entries = vec![ManifestEntry { hash: "ab12".to_string(), size: 10, path: PathBuf::from("a/b.txt") },
+                      ManifestEntry { hash: "cd34".to_string(), size: 10, path: PathBuf::from("a/b.txt-2") }];
+   assert_eq!(unique_path(&entries, Path::new("a/c.txt")).unwrap(), PathBuf::from("a/c.txt"));
+   assert_eq!(unique_path(&entries, Path::new("a/b.txt")).unwrap(), PathBuf::from("a/b.txt-3"));
+}
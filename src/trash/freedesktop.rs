@@ -0,0 +1,208 @@
+use std::io::{self, Write};
+use std::fs::{self, OpenOptions, DirBuilder};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use libc;
+
+use app_result::{AppResult, AppError, WithPath};
+use file_system::move_file;
+use date_time::DateTime;
+use appdirs;
+
+/// Moves `file` into the trash of the desktop following the freedesktop.org
+/// trash specification, together with a `.trashinfo` entry which records
+/// the original path and the deletion date of `file`.
+///
+/// Files on the volume of the home directory are put into the home trash
+/// `$XDG_DATA_HOME/Trash`, files on other volumes into the trash directory
+/// of their volume, `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid`. If there's
+/// no usable trash directory on the volume, then the home trash is used.
+///
+/// Returns the path of the trashed file inside of the trash.
+pub fn move_to_trash(file: &Path) -> AppResult<PathBuf>
+{
+   let file = absolute_path(file)?;
+   let file_dev = fs::symlink_metadata(&file)?.dev();
+
+   let home_trash = home_trash_dir()?;
+   if file_dev == device_of(&home_trash)? {
+      return trash_into(&file, &home_trash, None);
+   }
+
+   let top_dir = top_dir(&file, file_dev);
+   match volume_trash_dir(&top_dir) {
+      Ok(trash_dir) => trash_into(&file, &trash_dir, Some(&top_dir)),
+      Err(..)       => trash_into(&file, &home_trash, None)
+   }
+}
+
+/// The trash directory in the home directory of the user.
+pub fn home_trash_dir() -> AppResult<PathBuf>
+{
+   appdirs::data_home()
+      .map(|dir| dir.join("Trash"))
+      .ok_or(AppError::from_string("Couldn't get data directory!".to_string()))
+}
+
+// Puts `file` into `trash_dir/files` and writes its `.trashinfo` into `trash_dir/info`.
+// If `top_dir` is given, then the path of `file` is recorded relative to it.
+fn trash_into(file: &Path, trash_dir: &Path, top_dir: Option<&Path>) -> AppResult<PathBuf>
+{
+   let files_dir = trash_dir.join("files");
+   let info_dir  = trash_dir.join("info");
+   create_private_dir(&files_dir)?;
+   create_private_dir(&info_dir)?;
+
+   let filename = file.file_name()
+      .and_then(|f| f.to_str())
+      .ok_or(AppError::from_string(format!("Couldn't get filename_str of '{}'!", file.display())))?;
+
+   let orig_path = match top_dir.and_then(|dir| file.strip_prefix(dir).ok()) {
+      Some(rel_path) => rel_path,
+      None           => file
+   };
+
+   let info = format!("[Trash Info]\nPath={}\nDeletionDate={}\n",
+                      percent_encode(orig_path), DateTime::now());
+
+   // The info file is created exclusively to reserve the name inside of the trash.
+   for i in 1..10000 {
+      let name = if i == 1 { filename.to_string() } else { format!("{}-{}", filename, i) };
+      let trash_file = files_dir.join(&name);
+      if fs::symlink_metadata(&trash_file).is_ok() {
+         continue;
+      }
+
+      let info_file = info_dir.join(format!("{}.trashinfo", name));
+      match OpenOptions::new().write(true).create_new(true).open(&info_file) {
+         Ok(mut handle) => {
+            let result = handle.write_all(info.as_bytes())
+               .with_path(&info_file)
+               .and_then(|_| move_into_trash(file, &trash_file));
+
+            if result.is_err() {
+               let _ = fs::remove_file(&info_file);
+            }
+
+            return result.map(|_| trash_file);
+         }
+
+         Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+         Err(err) => return Err(AppError::io(err, &info_file))
+      }
+   }
+
+   Err(AppError::from_string(format!("Couldn't get a unique trash name for '{}'!", file.display())))
+}
+
+fn move_into_trash(file: &Path, trash_file: &Path) -> AppResult<()>
+{
+   match fs::rename(file, trash_file) {
+      Ok(..)  => Ok(()),
+      Err(..) => move_file(file, trash_file)
+   }
+}
+
+// The volume trash directory is `$topdir/.Trash/$uid` if `$topdir/.Trash` is
+// a directory with the sticky bit set, otherwise it's `$topdir/.Trash-$uid`.
+fn volume_trash_dir(top_dir: &Path) -> AppResult<PathBuf>
+{
+   let uid = unsafe { libc::getuid() };
+
+   let admin_dir = top_dir.join(".Trash");
+   if let Ok(meta) = fs::symlink_metadata(&admin_dir) {
+      if meta.is_dir() && meta.mode() & 0o1000 != 0 {
+         let user_dir = admin_dir.join(uid.to_string());
+         if create_private_dir(&user_dir).is_ok() {
+            return Ok(user_dir);
+         }
+      }
+   }
+
+   let user_dir = top_dir.join(format!(".Trash-{}", uid));
+   create_private_dir(&user_dir)?;
+
+   let meta = fs::symlink_metadata(&user_dir).with_path(&user_dir)?;
+   if ! meta.is_dir() || meta.uid() != uid {
+      return Err(AppError::from_string(format!("Invalid trash directory '{}'!", user_dir.display())));
+   }
+
+   Ok(user_dir)
+}
+
+// The top directory of the volume of `file`, the last parent with the device `dev`.
+fn top_dir(file: &Path, dev: u64) -> PathBuf
+{
+   let mut top_dir = file.parent().unwrap_or(file).to_path_buf();
+   for dir in file.ancestors().skip(1) {
+      match fs::metadata(dir) {
+         Ok(ref meta) if meta.dev() == dev => top_dir = dir.to_path_buf(),
+         _ => break
+      }
+   }
+
+   top_dir
+}
+
+// The device of `path` or of its nearest existing ancestor.
+fn device_of(path: &Path) -> AppResult<u64>
+{
+   for dir in path.ancestors() {
+      if let Ok(meta) = fs::metadata(dir) {
+         return Ok(meta.dev());
+      }
+   }
+
+   Err(AppError::from_string(format!("Couldn't get device of '{}'!", path.display())))
+}
+
+// Makes `file` absolute without resolving a symbolic link at `file` itself,
+// because the link and not its target should be trashed.
+fn absolute_path(file: &Path) -> AppResult<PathBuf>
+{
+   let filename = file.file_name()
+      .ok_or(AppError::from_string(format!("Couldn't get filename from path '{}'!", file.display())))?;
+
+   let parent = match file.parent() {
+      Some(parent) if ! parent.as_os_str().is_empty() => parent,
+      _ => Path::new(".")
+   };
+
+   Ok(fs::canonicalize(parent)?.join(filename))
+}
+
+fn create_private_dir(dir: &Path) -> AppResult<()>
+{
+   if dir.is_dir() {
+      return Ok(());
+   }
+
+   DirBuilder::new().recursive(true).mode(0o700).create(dir).with_path(dir)?;
+   Ok(())
+}
+
+// The trash specification requires the path to be escaped like an URL path.
+fn percent_encode(path: &Path) -> String
+{
+   let mut encoded = String::new();
+   for byte in path.as_os_str().as_bytes() {
+      match *byte {
+         b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/'
+         => encoded.push(*byte as char),
+
+         _ => encoded.push_str(&format!("%{:02X}", byte))
+      }
+   }
+
+   encoded
+}
+
+#[test]
+fn tests()
+{
+   assert_eq!(percent_encode(Path::new("/home/blub/a b.txt")), "/home/blub/a%20b.txt");
+   assert_eq!(percent_encode(Path::new("x (blub's conflicted copy).txt")),
+              "x%20%28blub%27s%20conflicted%20copy%29.txt");
+   assert_eq!(percent_encode(Path::new("ä")), "%C3%A4");
+}
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::fmt::{Display, Formatter, Error};
+
+use app_result::AppResult;
+
+pub use self::Trash::{
+   Private,
+   Freedesktop
+};
+
+pub use self::private::{
+   PrivateTrash,
+   sessions,
+   restore
+};
+
+pub use self::purge::{
+   stats,
+   purge,
+   parse_age,
+   parse_size,
+   format_size
+};
+
+mod private;
+mod purge;
+mod dedup;
+
+#[cfg(unix)]
+mod freedesktop;
+
+/// Where the trashed files are put into.
+pub enum Trash
+{
+   /// the private trash directory of confsolve
+   Private(PrivateTrash),
+
+   /// the trash of the desktop following the freedesktop.org trash specification
+   Freedesktop
+}
+
+impl Trash
+{
+   /// Moves `file` into the trash and returns where it has been put into.
+   pub fn put(&self, file: &Path) -> AppResult<PathBuf>
+   {
+      match *self {
+         Private(ref trash) => trash.put(file),
+         Freedesktop      => put_into_freedesktop_trash(file)
+      }
+   }
+
+   /// If `file` is inside of the trash, like files put into a trash
+   /// directory, which is inside of a watched directory.
+   pub fn contains(&self, file: &Path) -> bool
+   {
+      match *self {
+         Private(ref trash) => trash.contains(file),
+         Freedesktop        => {
+            freedesktop_trash_dir().map(|dir| file.starts_with(dir)).unwrap_or(false)
+               || file.iter().any(|name| name == ".Trash" || name.to_string_lossy().starts_with(".Trash-"))
+         }
+      }
+   }
+}
+
+#[cfg(unix)]
+fn put_into_freedesktop_trash(file: &Path) -> AppResult<PathBuf>
+{
+   freedesktop::move_to_trash(file)
+}
+
+#[cfg(not(unix))]
+fn put_into_freedesktop_trash(_file: &Path) -> AppResult<PathBuf>
+{
+   use app_result::AppError;
+   Err(AppError::from_string("The freedesktop.org trash is only supported on unix!".to_string()))
+}
+
+impl Display for Trash
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Private(ref trash) => write!(f, "the trash directory '{}'", trash.session_dir().display()),
+
+         Freedesktop => {
+            match freedesktop_trash_dir() {
+               Some(dir) => write!(f, "the desktop trash '{}'", dir.display()),
+               None      => write!(f, "the desktop trash")
+            }
+         }
+      }
+   }
+}
+
+#[cfg(unix)]
+fn freedesktop_trash_dir() -> Option<PathBuf>
+{
+   freedesktop::home_trash_dir().ok()
+}
+
+#[cfg(not(unix))]
+fn freedesktop_trash_dir() -> Option<PathBuf>
+{
+   None
+}
@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+use std::fs::{read_dir, create_dir_all, copy, canonicalize};
+
+use app_result::{AppResult, AppError, WithPath};
+use file_system::{walk_files, move_file, unique_file};
+use date_time::DateTime;
+use super::dedup::{self, MANIFEST};
+
+/// The private trash directory of confsolve.
+///
+/// Every run of confsolve gets its own session directory inside of the trash
+/// directory, named by the start time of the run. Inside of the session directory
+/// the trashed files keep their path relative to the start directory of the run:
+///
+///    `<trash_dir>/<YYYY-MM-DD_hh-mm-ss>/<relative path>`
+///
+/// With `dedup` the contents of the trashed files are stored only once, and
+/// the session directory only contains a manifest mapping the relative paths
+/// of the trashed files to their contents.
+pub struct PrivateTrash
+{
+   dir      : PathBuf,
+   session  : String,
+   start_dir: PathBuf,
+   dedup    : bool
+}
+
+impl PrivateTrash
+{
+   pub fn new(dir: PathBuf, start_dir: &Path, dedup: bool) -> PrivateTrash
+   {
+      PrivateTrash {
+         dir,
+         session: DateTime::now().dir_name(),
+         start_dir: start_dir.to_path_buf(),
+         dedup
+      }
+   }
+
+   /// The directory of the current session, which is created
+   /// with the first file put into the trash.
+   pub fn session_dir(&self) -> PathBuf
+   {
+      self.dir.join(&self.session)
+   }
+
+   /// If `file` is inside of the trash directory.
+   pub fn contains(&self, file: &Path) -> bool
+   {
+      file.starts_with(&self.dir) || canonicalize(&self.dir).map(|dir| file.starts_with(dir)).unwrap_or(false)
+   }
+
+   /// Moves `file` into the session directory and returns where it has been put into.
+   pub fn put(&self, file: &Path) -> AppResult<PathBuf>
+   {
+      let filename = file.file_name()
+         .ok_or(AppError::from_string(format!("Couldn't get filename from path '{}'!", file.display())))?;
+
+      let rel_path = match file.strip_prefix(&self.start_dir) {
+         Ok(rel_path) if rel_path.file_name().is_some() => rel_path,
+         _ => Path::new(filename)
+      };
+
+      if self.dedup {
+         return dedup::store(&self.dir, &self.session_dir(), file, rel_path);
+      }
+
+      let trash_file = unique_file(&self.session_dir().join(rel_path))?;
+      create_parent_dir(&trash_file)?;
+      move_file(file, &trash_file)?;
+      Ok(trash_file)
+   }
+}
+
+/// The files trashed by one run of confsolve.
+pub struct Session
+{
+   /// the name of the session directory, which is the start time of the run,
+   /// or an empty string for files put into the trash without a session
+   pub name : String,
+
+   /// the paths of the trashed files relative to the session directory
+   pub files: Vec<PathBuf>
+}
+
+/// Returns the sessions of the trash directory `trash_dir`, the oldest one first.
+/// Directories which aren't named like a session are skipped, like by `purge`.
+pub fn sessions(trash_dir: &Path) -> AppResult<Vec<Session>>
+{
+   let mut sessions = Vec::new();
+   let mut loose_files = Vec::new();
+   for entry in read_dir(trash_dir).with_path(trash_dir)? {
+      let path = entry.with_path(trash_dir)?.path();
+      let name = match path.file_name().and_then(|n| n.to_str()) {
+         Some(name) if ! name.starts_with('.') => name.to_string(),
+         _ => continue
+      };
+
+      if path.is_dir() {
+         if DateTime::from_dir_name(&name).is_none() {
+            continue;
+         }
+
+         let mut files = session_files(&path)?;
+         files.sort();
+         sessions.push(Session { name, files });
+      }
+      else if path.is_file() {
+         loose_files.push(PathBuf::from(name));
+      }
+   }
+
+   sessions.sort_by(|s1, s2| s1.name.cmp(&s2.name));
+   if ! loose_files.is_empty() {
+      loose_files.sort();
+      sessions.insert(0, Session { name: String::new(), files: loose_files });
+   }
+
+   Ok(sessions)
+}
+
+/// Copies the files of the session `session` of the trash directory `trash_dir`
+/// into the directory `to_dir`, keeping their relative paths. Existing files
+/// aren't overwritten, instead the restored file gets a unique name.
+/// Returns the number of restored files.
+pub fn restore(trash_dir: &Path, session: &str, to_dir: &Path) -> AppResult<usize>
+{
+   let session_dir = trash_dir.join(session);
+   if session.is_empty() || session.starts_with('.') || ! session_dir.is_dir() {
+      return Err(AppError::from_string(format!("Couldn't find session '{}' in trash directory '{}'!",
+                                               session, trash_dir.display())));
+   }
+
+   let mut num_restored = 0;
+   for file in plain_files(&session_dir)? {
+      let to_file = unique_file(&to_dir.join(&file))?;
+      create_parent_dir(&to_file)?;
+      copy(session_dir.join(&file), &to_file).with_path(&to_file)?;
+      num_restored += 1;
+   }
+
+   for entry in dedup::read_manifest(&session_dir)? {
+      let to_file = unique_file(&to_dir.join(&entry.path))?;
+      create_parent_dir(&to_file)?;
+      dedup::restore(trash_dir, &entry, &to_file)?;
+      num_restored += 1;
+   }
+
+   Ok(num_restored)
+}
+
+/// The paths of all files of the session directory `session_dir` relative to it,
+/// the ones directly stored in it and the ones recorded in its manifest.
+pub fn session_files(session_dir: &Path) -> AppResult<Vec<PathBuf>>
+{
+   let mut files = plain_files(session_dir)?;
+   files.extend(dedup::read_manifest(session_dir)?.into_iter().map(|e| e.path));
+   Ok(files)
+}
+
+// The paths of the files directly stored in the session directory relative to it.
+fn plain_files(session_dir: &Path) -> AppResult<Vec<PathBuf>>
+{
+   Ok(walk_files(session_dir)?
+      .filter_map(|f| f.strip_prefix(session_dir).ok().map(|f| f.to_path_buf()))
+      .filter(|f| f.as_os_str() != MANIFEST)
+      .collect())
+}
+
+fn create_parent_dir(file: &Path) -> AppResult<()>
+{
+   if let Some(parent) = file.parent() {
+      if ! parent.is_dir() {
+         create_dir_all(parent).with_path(parent)?;
+      }
+   }
+
+   Ok(())
+}
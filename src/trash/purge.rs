@@ -0,0 +1,319 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::fs::{read_dir, remove_dir_all, remove_file};
+
+use app_result::{AppResult, WithPath};
+use file_system::walk_files;
+use date_time::DateTime;
+use parser::Parser;
+use super::dedup::{self, MANIFEST};
+
+/// Statistics about the private trash directory.
+#[derive(Default)]
+pub struct Stats
+{
+   pub sessions: usize,
+
+   /// the trashed files, including the ones without a session
+   pub files   : usize,
+
+   /// the size of the trashed files
+   pub bytes   : u64,
+
+   /// the size the trashed files are taking on disk, which is
+   /// smaller than `bytes` if the contents of files are deduplicated
+   pub stored_bytes: u64,
+
+   pub oldest  : Option<String>,
+   pub newest  : Option<String>
+}
+
+/// What was removed by `purge`.
+#[derive(Default)]
+pub struct Purged
+{
+   pub sessions: usize,
+   pub files   : usize,
+   pub bytes   : u64
+}
+
+// A session directory or a file without a session, which are the units removed by `purge`.
+struct Entry
+{
+   path    : PathBuf,
+
+   // the name of the session directory, none for a file without a session
+   session : Option<String>,
+
+   age_secs: u64,
+   files   : usize,
+   bytes   : u64,
+
+   // the size of the files which aren't stored as deduplicated blobs
+   plain_bytes: u64
+}
+
+/// Returns the statistics of the trash directory `trash_dir`.
+pub fn stats(trash_dir: &Path) -> AppResult<Stats>
+{
+   let mut stats = Stats::default();
+   for entry in entries(trash_dir)? {
+      stats.files += entry.files;
+      stats.bytes += entry.bytes;
+      stats.stored_bytes += entry.plain_bytes;
+      if let Some(session) = entry.session {
+         stats.sessions += 1;
+         if stats.oldest.is_none() {
+            stats.oldest = Some(session.clone());
+         }
+
+         stats.newest = Some(session);
+      }
+   }
+
+   stats.stored_bytes += dedup::blobs_size(trash_dir)?;
+   Ok(stats)
+}
+
+/// Removes the sessions of the trash directory `trash_dir` which are older than
+/// `older_than` seconds, and then the oldest sessions until the size the trash
+/// directory takes on disk is at most `max_size` bytes. Deduplicated contents are
+/// removed as soon as no session references them anymore. The files without a
+/// session - put directly into the trash directory - are purged by their
+/// modification time, each one like a session of its own.
+pub fn purge(trash_dir: &Path, older_than: Option<u64>, max_size: Option<u64>) -> AppResult<Purged>
+{
+   let entries = entries(trash_dir)?;
+   let mut size = entries.iter().fold(0, |size, e| size + e.plain_bytes) + dedup::blobs_size(trash_dir)?;
+   let mut purged = Purged::default();
+   for entry in entries.iter() {
+      let too_old = older_than.map(|secs| entry.age_secs > secs).unwrap_or(false);
+      let too_big = max_size.map(|bytes| size > bytes).unwrap_or(false);
+      if ! too_old && ! too_big {
+         continue;
+      }
+
+      if entry.session.is_some() {
+         remove_dir_all(&entry.path).with_path(&entry.path)?;
+         purged.sessions += 1;
+      }
+      else {
+         remove_file(&entry.path).with_path(&entry.path)?;
+      }
+
+      size = size.saturating_sub(entry.plain_bytes);
+
+      // the contents shared with other sessions are still on disk
+      if entry.bytes > entry.plain_bytes {
+         size = size.saturating_sub(dedup::collect_garbage(trash_dir)?);
+      }
+
+      purged.files += entry.files;
+      purged.bytes += entry.bytes;
+   }
+
+   if purged.files > 0 {
+      dedup::collect_garbage(trash_dir)?;
+   }
+
+   Ok(purged)
+}
+
+/// Parses an age like `30d`, with one of the units `s`, `m`, `h`, `d` or `w`,
+/// into seconds. A number without a unit are days.
+pub fn parse_age(input: &str) -> Option<u64>
+{
+   let (num, unit, suffix) = parse_num_and_unit(input)?;
+   if ! suffix.is_empty() {
+      return None;
+   }
+
+   let secs = match unit {
+      Some('s')        => 1,
+      Some('m')        => 60,
+      Some('h')        => 60 * 60,
+      Some('d') | None => 24 * 60 * 60,
+      Some('w')        => 7 * 24 * 60 * 60,
+      _                => return None
+   };
+
+   num.checked_mul(secs)
+}
+
+/// Parses a size like `2G`, with one of the units `K`, `M`, `G` or `T`
+/// as powers of 1024, into bytes. A number without a unit are bytes.
+pub fn parse_size(input: &str) -> Option<u64>
+{
+   let (num, unit, suffix) = parse_num_and_unit(input)?;
+   let bytes: u64 = match unit {
+      None      => 1,
+      Some('b') => 1,
+      Some('k') => 1 << 10,
+      Some('m') => 1 << 20,
+      Some('g') => 1 << 30,
+      Some('t') => 1 << 40,
+      _         => return None
+   };
+
+   // allows units like `GB` or `GiB`
+   let is_byte_suffix = (suffix == "b" || suffix == "ib") && unit.map(|u| "kmgt".contains(u)).unwrap_or(false);
+   if ! suffix.is_empty() && ! is_byte_suffix {
+      return None;
+   }
+
+   num.checked_mul(bytes)
+}
+
+/// Formats `bytes` with a binary unit, e.g. `1.5 GiB`.
+pub fn format_size(bytes: u64) -> String
+{
+   let units = ["KiB", "MiB", "GiB", "TiB"];
+   if bytes < 1024 {
+      return format!("{} B", bytes);
+   }
+
+   let mut size = bytes as f64 / 1024.0;
+   let mut unit = 0;
+   while size >= 1024.0 && unit + 1 < units.len() {
+      size /= 1024.0;
+      unit += 1;
+   }
+
+   format!("{:.1} {}", size, units[unit])
+}
+
+// The number, the unit and the rest after the unit of `input`, in lowercase.
+fn parse_num_and_unit(input: &str) -> Option<(u64, Option<char>, String)>
+{
+   let lowercase_input = input.trim().to_lowercase();
+   let mut parser = Parser::new(&lowercase_input);
+   let num = parser.take_uint().ok()? as u64;
+   let unit = parser.take_char().ok();
+   Some((num, unit, parser.take_till_eof()))
+}
+
+// The session directories and the files without a session of the trash directory,
+// the oldest one first. Other directories - like the ones of the user, if the trash
+// directory has been set to one of their directories - and hidden files are left
+// alone, the deduplicated contents are removed by `dedup::collect_garbage`.
+fn entries(trash_dir: &Path) -> AppResult<Vec<Entry>>
+{
+   let now = DateTime::now();
+   let mut entries = Vec::new();
+   for dir_entry in read_dir(trash_dir).with_path(trash_dir)? {
+      let dir_entry = dir_entry.with_path(trash_dir)?;
+      let path = dir_entry.path();
+      let file_type = dir_entry.file_type().with_path(&path)?;
+      let name = match path.file_name().and_then(|n| n.to_str()) {
+         Some(name) if ! name.starts_with('.') => name.to_string(),
+         _                                     => continue
+      };
+
+      if file_type.is_file() {
+         let meta = dir_entry.metadata().with_path(&path)?;
+         let age_secs = meta.modified().ok()
+            .and_then(|time| SystemTime::now().duration_since(time).ok())
+            .map(|age| age.as_secs())
+            .unwrap_or(0);
+
+         entries.push(Entry { path, session: None, age_secs, files: 1, bytes: meta.len(), plain_bytes: meta.len() });
+         continue;
+      }
+
+      let date_time = match DateTime::from_dir_name(&name).filter(|_| file_type.is_dir()) {
+         Some(date_time) => date_time,
+         None            => continue
+      };
+
+      let mut files = 0;
+      let mut plain_bytes = 0;
+      for file in walk_files(&path)? {
+         if file.strip_prefix(&path).map(|f| f.as_os_str() != MANIFEST).unwrap_or(true) {
+            files += 1;
+            plain_bytes += file.metadata().map(|m| m.len()).unwrap_or(0);
+         }
+      }
+
+      let mut bytes = plain_bytes;
+      for manifest_entry in dedup::read_manifest(&path)? {
+         files += 1;
+         bytes += manifest_entry.size;
+      }
+
+      let age_secs = date_time.seconds_until(&now).max(0) as u64;
+      entries.push(Entry { path, session: Some(name), age_secs, files, bytes, plain_bytes });
+   }
+
+   entries.sort_by(|e1, e2| e2.age_secs.cmp(&e1.age_secs).then(e1.session.cmp(&e2.session)));
+   Ok(entries)
+}
+
+#[test]
+fn tests()
+{
+   assert_eq!(parse_age("30d"), Some(30 * 24 * 60 * 60));
+   assert_eq!(parse_age("30"), Some(30 * 24 * 60 * 60));
+   assert_eq!(parse_age("2w"), Some(14 * 24 * 60 * 60));
+   assert_eq!(parse_age("12h"), Some(12 * 60 * 60));
+   assert_eq!(parse_age("5m"), Some(5 * 60));
+   assert_eq!(parse_age("5s"), Some(5));
+   assert_eq!(parse_age("5x"), None);
+   assert_eq!(parse_age("30dib"), None);
+   assert_eq!(parse_age("5hb"), None);
+   assert_eq!(parse_age("5sb"), None);
+   assert_eq!(parse_age("d"), None);
+   assert_eq!(parse_age(""), None);
+
+   assert_eq!(parse_size("2G"), Some(2 * 1024 * 1024 * 1024));
+   assert_eq!(parse_size("2GB"), Some(2 * 1024 * 1024 * 1024));
+   assert_eq!(parse_size("2GiB"), Some(2 * 1024 * 1024 * 1024));
+   assert_eq!(parse_size("500m"), Some(500 * 1024 * 1024));
+   assert_eq!(parse_size("10K"), Some(10 * 1024));
+   assert_eq!(parse_size("100"), Some(100));
+   assert_eq!(parse_size("100B"), Some(100));
+   assert_eq!(parse_size("2Gx"), None);
+   assert_eq!(parse_size("100BB"), None);
+   assert_eq!(parse_size("100Bib"), None);
+   assert_eq!(parse_size("G"), None);
+
+   assert_eq!(format_size(100), "100 B");
+   assert_eq!(format_size(1536), "1.5 KiB");
+   assert_eq!(format_size(2 * 1024 * 1024 * 1024), "2.0 GiB");
+
+   // only the session directories and the files without a session are purged
+   let trash_dir = ::std::env::temp_dir().join(format!("confsolve-purge-test-{}", ::std::process::id()));
+   let _ = remove_dir_all(&trash_dir);
+   for file in ["2004-08-31_02-03-08/a.conf", "notes.txt", ".hidden", "photos/b.jpg", "2004-08-31_02-03-08-x/c.conf"].iter() {
+      let file = trash_dir.join(file);
+      ::std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+      ::std::fs::write(&file, "x").unwrap();
+   }
+
+   let trash_stats = stats(&trash_dir).unwrap();
+   assert_eq!((trash_stats.sessions, trash_stats.files, trash_stats.bytes), (1, 2, 2));
+   assert_eq!(purge(&trash_dir, Some(1000), None).unwrap().files, 1);
+   assert!(trash_dir.join("notes.txt").exists());
+
+   let purged = purge(&trash_dir, None, Some(0)).unwrap();
+   assert_eq!((purged.sessions, purged.files), (0, 1));
+   assert!(! trash_dir.join("2004-08-31_02-03-08").exists());
+   assert!(! trash_dir.join("notes.txt").exists());
+   assert!(trash_dir.join(".hidden").exists());
+   assert!(trash_dir.join("photos/b.jpg").exists());
+   assert!(trash_dir.join("2004-08-31_02-03-08-x/c.conf").exists());
+   let _ = remove_dir_all(&trash_dir);
+
+   // the deduplicated contents are counted once for the size on disk
+   for session in ["2004-08-31_02-03-08", "2004-09-01_02-03-08"].iter() {
+      let file = trash_dir.join("a.conf");
+      ::std::fs::create_dir_all(&trash_dir).unwrap();
+      ::std::fs::write(&file, [b'x'; 1000].as_ref()).unwrap();
+      dedup::store(&trash_dir, &trash_dir.join(session), &file, Path::new("a.conf")).unwrap();
+   }
+
+   assert_eq!(stats(&trash_dir).unwrap().bytes, 2000);
+   assert_eq!(purge(&trash_dir, None, Some(1500)).unwrap().sessions, 0);
+   assert_eq!(purge(&trash_dir, None, Some(500)).unwrap().sessions, 2);
+   assert_eq!(dedup::blobs_size(&trash_dir).unwrap(), 0);
+   let _ = remove_dir_all(&trash_dir);
+}
@@ -1,5 +1,6 @@
 use parser::Parser;
 use std::iter::FromIterator;
+use std::fmt::{Display, Formatter, Error};
 
 pub use self::UserReply::{
    TakeFile,
@@ -7,6 +8,9 @@ pub use self::UserReply::{
    ShowDiff,
    ShowDiffWith,
    ShowDiffBetween,
+   ShowDiffAll,
+   Merge,
+   Combine,
    Skip,
    Quit,
    Help
@@ -22,12 +26,142 @@ pub enum UserReply
    ShowDiff,
    ShowDiffWith(FileNum),
    ShowDiffBetween(FileNum, FileNum),
+   ShowDiffAll,
+   Merge(FileNum),
+   Combine(FileNum),
    Skip,
    Quit,
    Help
 }
 
-pub fn parse(input: &String, num_conf_files: usize) -> Option<UserReply>
+// Formats the reply as the input the user has to type for it.
+impl Display for UserReply
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         TakeFile(num)                 => write!(f, "t{}", num),
+         MoveToTrash                   => write!(f, "m"),
+         ShowDiff                      => write!(f, "d"),
+         ShowDiffWith(num)             => write!(f, "d{}", num),
+         ShowDiffBetween(num1, num2)   => write!(f, "d{} {}", num1, num2),
+         ShowDiffAll                   => write!(f, "da"),
+         Merge(num)                    => write!(f, "e{}", num),
+         Combine(num)                  => write!(f, "c{}", num),
+         Skip                          => write!(f, "s"),
+         Quit                          => write!(f, "q"),
+         Help                          => write!(f, "h")
+      }
+   }
+}
+
+/// The reply of the user after a file operation of a conflict failed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorReply
+{
+   /// execute the failed operation again
+   Retry,
+
+   /// leave the conflict unresolved and show the next one
+   SkipConflict,
+
+   /// quit the application
+   QuitAfterError
+}
+
+// Formats the reply as the input the user has to type for it.
+impl Display for ErrorReply
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         ErrorReply::Retry          => write!(f, "r"),
+         ErrorReply::SkipConflict   => write!(f, "s"),
+         ErrorReply::QuitAfterError => write!(f, "q")
+      }
+   }
+}
+
+pub fn parse_error_reply(input: &str) -> Option<ErrorReply>
+{
+   match input.trim().to_lowercase().as_str() {
+      "r" => Some(ErrorReply::Retry),
+      "s" => Some(ErrorReply::SkipConflict),
+      "q" => Some(ErrorReply::QuitAfterError),
+      _   => None
+   }
+}
+
+/// The keys of the replies, which can be changed in the configuration.
+/// The replies are logged and displayed with the default keys.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keys
+{
+   pub take : char,
+   pub trash: char,
+   pub diff : char,
+   pub merge: char,
+   pub combine: char,
+   pub skip : char,
+   pub quit : char,
+   pub help : char
+}
+
+impl Default for Keys
+{
+   fn default() -> Keys
+   {
+      Keys { take: 't', trash: 'm', diff: 'd', merge: 'e', combine: 'c', skip: 's', quit: 'q', help: 'h' }
+   }
+}
+
+impl Keys
+{
+   /// The names and the keys of all replies.
+   pub fn all(&self) -> [(&'static str, char); 8]
+   {
+      [("take", self.take), ("trash", self.trash), ("diff", self.diff), ("merge", self.merge),
+       ("combine", self.combine), ("skip", self.skip), ("quit", self.quit), ("help", self.help)]
+   }
+
+   /// Checks that every key is a distinct lowercase letter.
+   pub fn validate(&self) -> Result<(), String>
+   {
+      let all = self.all();
+      for (i, &(name, key)) in all.iter().enumerate() {
+         if ! key.is_ascii_lowercase() {
+            return Err(format!("The key '{}' for '{}' isn't a lowercase letter", key, name));
+         }
+
+         if let Some(&(other, _)) = all[..i].iter().find(|&&(_, k)| k == key) {
+            return Err(format!("The key '{}' is used for '{}' and '{}'", key, other, name));
+         }
+      }
+
+      Ok(())
+   }
+}
+
+/// The prompt asking for a reply, with the key of every reply highlighted.
+pub fn prompt(keys: &Keys) -> String
+{
+   format!("{} (NUM) | {} | Show {} (NUM [NUM], A) | {} (NUM) | {} (NUM) | {} | {} | {}: ",
+           label("Take File", keys.take), label("Move to Trash", keys.trash), label("Diff", keys.diff),
+           label("Merge", keys.merge), label("Combine", keys.combine), label("Skip", keys.skip),
+           label("Quit", keys.quit), label("Help", keys.help))
+}
+
+/// Puts the first occurrence of `key` in `name` into parentheses, e.g. `(T)ake File`,
+/// or appends the key in brackets, if `name` doesn't contain it.
+pub fn label(name: &str, key: char) -> String
+{
+   match name.char_indices().find(|&(_, c)| c.to_ascii_lowercase() == key) {
+      Some((i, c)) => format!("{}({}){}", &name[..i], c, &name[i + c.len_utf8()..]),
+      None         => format!("{} [{}]", name, key)
+   }
+}
+
+pub fn parse(input: &str, num_conf_files: usize, keys: &Keys) -> Option<UserReply>
 {
    if input.is_empty() || num_conf_files == 0 {
       return None;
@@ -40,11 +174,17 @@ pub fn parse(input: &String, num_conf_files: usize) -> Option<UserReply>
       Err(..) => None,
 
       Ok(c)   => {
+         // the difference between all files
+         if c == keys.diff && parser.skip("a").is_ok() {
+            parser.skip_whitespace();
+            return if parser.eof() { Some(ShowDiffAll) } else { None };
+         }
+
          parser.skip_whitespace();
          let nothing_left = parser.eof();
          let uints = take_uints(&mut parser);
          match c {
-            't' => {
+            c if c == keys.take => {
                match uints.len() {
                   1 if valid_file_num(uints[0], num_conf_files)
                   => Some(TakeFile(uints[0])),
@@ -53,7 +193,7 @@ pub fn parse(input: &String, num_conf_files: usize) -> Option<UserReply>
                }
             }
 
-            'd' => {
+            c if c == keys.diff => {
                match uints.len() {
                   0 if num_conf_files == 1
                   => Some(ShowDiff),
@@ -68,10 +208,34 @@ pub fn parse(input: &String, num_conf_files: usize) -> Option<UserReply>
                }
             }
 
-            'm'      if nothing_left => Some(MoveToTrash),
-            's'      if nothing_left => Some(Skip),
-            'q'      if nothing_left => Some(Quit),
-            'h' |'?' if nothing_left => Some(Help),
+            c if c == keys.merge => {
+               match uints.len() {
+                  0 if num_conf_files == 1
+                  => Some(Merge(1)),
+
+                  1 if valid_file_num(uints[0], num_conf_files)
+                  => Some(Merge(uints[0])),
+
+                  _ => None
+               }
+            }
+
+            c if c == keys.combine => {
+               match uints.len() {
+                  0 if num_conf_files == 1
+                  => Some(Combine(1)),
+
+                  1 if valid_file_num(uints[0], num_conf_files)
+                  => Some(Combine(uints[0])),
+
+                  _ => None
+               }
+            }
+
+            c if c == keys.trash && nothing_left => Some(MoveToTrash),
+            c if c == keys.skip  && nothing_left => Some(Skip),
+            c if c == keys.quit  && nothing_left => Some(Quit),
+            c if (c == keys.help || c == '?') && nothing_left => Some(Help),
 
             _ => None,
          }
@@ -123,11 +287,45 @@ fn tests()
    test_str("qq"      , None);
    test_str("h"       , Some(Help));
    test_str("?"       , Some(Help));
+   test_str("e3"      , Some(Merge(3)));
+   test_str("da"      , Some(ShowDiffAll));
+   test_str(" DA "    , Some(ShowDiffAll));
+   test_str("da1"     , None);
+   test_str("e"       , None);
+   test_str("c2"      , Some(Combine(2)));
+   test_str("c"       , None);
+
+   let keys = Keys::default();
+   for reply in [TakeFile(2), MoveToTrash, ShowDiffWith(3), ShowDiffBetween(1, 2), ShowDiffAll, Merge(2), Combine(2), Skip, Quit, Help] {
+      assert_eq!(parse(&reply.to_string(), 12, &keys), Some(reply));
+   }
+
+   assert_eq!(parse(&ShowDiff.to_string(), 1, &keys), Some(ShowDiff));
+   assert_eq!(parse("e", 1, &keys), Some(Merge(1)));
+
+   let keys = Keys { take: 'u', quit: 'x', ..Keys::default() };
+   assert_eq!(parse("u2", 12, &keys), Some(TakeFile(2)));
+   assert_eq!(parse("x", 12, &keys), Some(Quit));
+   assert_eq!(parse("t2", 12, &keys), None);
+   assert!(keys.validate().is_ok());
+   assert!(Keys { skip: 't', ..Keys::default() }.validate().is_err());
+   assert!(Keys { skip: '1', ..Keys::default() }.validate().is_err());
+
+   assert_eq!(prompt(&Keys::default()),
+              "(T)ake File (NUM) | (M)ove to Trash | Show (D)iff (NUM [NUM], A) | M(e)rge (NUM) | (C)ombine (NUM) | (S)kip | (Q)uit | (H)elp: ");
+   assert!(prompt(&keys).starts_with("Take File [u] (NUM) | "));
+
+   for reply in [ErrorReply::Retry, ErrorReply::SkipConflict, ErrorReply::QuitAfterError] {
+      assert_eq!(parse_error_reply(&reply.to_string()), Some(reply));
+   }
+
+   assert_eq!(parse_error_reply(" R \n"), Some(ErrorReply::Retry));
+   assert_eq!(parse_error_reply("t1"), None);
 }
 
 #[cfg(test)]
 fn test_str(input: &str, reply: Option<UserReply>)
 {
    println!("test: {}", input);
-   assert_eq!(parse(&input.to_string(), 12), reply);
+   assert_eq!(parse(input, 12, &Keys::default()), reply);
 }
@@ -0,0 +1,157 @@
+use std::io;
+use std::mem;
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::fs::read_dir;
+use libc;
+
+use app_result::{AppResult, AppError, WithPath};
+use file_system::walk_files;
+
+// a created or written file, a file or a directory moved into a watched
+// directory, a created directory, which has to be watched too, and a directory
+// moved out of a watched directory, whose watches have the wrong paths afterwards
+const EVENTS: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_MOVED_FROM;
+
+/// Watches a directory hierarchy with inotify for new or changed files.
+pub struct Watcher
+{
+   fd       : libc::c_int,
+   start_dir: PathBuf,
+
+   // the watched directories by their watch descriptor
+   dirs     : HashMap<libc::c_int, PathBuf>
+}
+
+impl Watcher
+{
+   /// Watches `start_dir` and all of its subdirectories.
+   pub fn new(start_dir: &Path) -> AppResult<Watcher>
+   {
+      let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+      if fd < 0 {
+         return Err(AppError::from(io::Error::last_os_error()));
+      }
+
+      let mut watcher = Watcher { fd, start_dir: start_dir.to_path_buf(), dirs: HashMap::new() };
+      watcher.add_dirs(start_dir)?;
+      Ok(watcher)
+   }
+
+   /// Blocks until files inside of the watched hierarchy have been written or
+   /// moved into it, and returns them. New directories are watched as well, and
+   /// the files already inside of them are returned. If the kernel dropped events,
+   /// all files of the hierarchy are returned. A renamed directory is watched by
+   /// its new path.
+   pub fn wait(&mut self) -> AppResult<Vec<PathBuf>>
+   {
+      let mut buffer = [0u8; 64 * 1024];
+      let len = loop {
+         let len = unsafe { libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+         if len >= 0 {
+            break len as usize;
+         }
+
+         let err = io::Error::last_os_error();
+         if err.kind() != io::ErrorKind::Interrupted {
+            return Err(AppError::from(err));
+         }
+      };
+
+      let mut files = Vec::new();
+      let header_len = mem::size_of::<libc::inotify_event>();
+      let mut offset = 0;
+      while offset + header_len <= len {
+         let event = unsafe { (buffer.as_ptr().add(offset) as *const libc::inotify_event).read_unaligned() };
+         let name_start = offset + header_len;
+         let name_end = name_start + event.len as usize;
+         offset = name_end;
+
+         if event.mask & libc::IN_Q_OVERFLOW != 0 {
+            return Ok(walk_files(&self.start_dir)?.collect());
+         }
+
+         if event.mask & libc::IN_IGNORED != 0 {
+            self.dirs.remove(&event.wd);
+            continue;
+         }
+
+         let dir = match self.dirs.get(&event.wd) {
+            Some(dir) => dir.clone(),
+            None      => continue
+         };
+
+         // the name is padded with null bytes
+         let name = &buffer[name_start..name_end.min(len)];
+         let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+         if name.is_empty() {
+            continue;
+         }
+
+         let path = dir.join(OsStr::from_bytes(name));
+         if event.mask & libc::IN_ISDIR != 0 && event.mask & libc::IN_MOVED_FROM != 0 {
+            // watched again by the event of the new path, if it's still inside of the hierarchy
+            self.remove_dirs(&path);
+         }
+         else if event.mask & libc::IN_ISDIR != 0 {
+            // a directory might be removed before it's watched
+            if self.add_dirs(&path).is_ok() {
+               files.extend(walk_files(&path)?);
+            }
+         }
+         else if event.mask & (libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) != 0 {
+            files.push(path);
+         }
+      }
+
+      Ok(files)
+   }
+
+   // Watches `dir` and all of its subdirectories.
+   fn add_dirs(&mut self, dir: &Path) -> AppResult<()>
+   {
+      let c_dir = CString::new(dir.as_os_str().as_bytes())
+         .map_err(|_| AppError::from_string(format!("Invalid directory '{}'!", dir.display())))?;
+
+      let wd = unsafe { libc::inotify_add_watch(self.fd, c_dir.as_ptr(), EVENTS | libc::IN_ONLYDIR) };
+      if wd < 0 {
+         return Err(AppError::io(io::Error::last_os_error(), dir));
+      }
+
+      self.dirs.insert(wd, dir.to_path_buf());
+      for entry in read_dir(dir).with_path(dir)? {
+         let path = entry.with_path(dir)?.path();
+         let is_dir = path.symlink_metadata().map(|m| m.is_dir()).unwrap_or(false);
+         if is_dir {
+            // unreadable subdirectories are skipped like by the scan
+            let _ = self.add_dirs(&path);
+         }
+      }
+
+      Ok(())
+   }
+
+   // Stops watching `dir` and all of its subdirectories.
+   fn remove_dirs(&mut self, dir: &Path)
+   {
+      let wds = self.dirs.iter()
+         .filter(|&(_, watched)| watched.starts_with(dir))
+         .map(|(wd, _)| *wd)
+         .collect::<Vec<libc::c_int>>();
+
+      for wd in wds {
+         unsafe { libc::inotify_rm_watch(self.fd, wd); }
+         self.dirs.remove(&wd);
+      }
+   }
+}
+
+impl Drop for Watcher
+{
+   fn drop(&mut self)
+   {
+      unsafe { libc::close(self.fd); }
+   }
+}
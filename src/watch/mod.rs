@@ -0,0 +1,131 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::fs::{self, OpenOptions, create_dir_all};
+use std::fmt::{Display, Formatter, Error};
+
+use app_result::{AppResult, AppError, WithPath};
+use file_conflict::ConflictType;
+use file_system::random_suffix;
+use appdirs;
+
+#[cfg(target_os = "linux")]
+pub use self::inotify::Watcher;
+
+#[cfg(target_os = "linux")]
+mod inotify;
+
+/// What the watch mode does with a new conflict.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Policy
+{
+   /// report the conflict
+   Notify,
+
+   /// queue the conflict for the next interactive session
+   Queue,
+
+   /// keep the original file and move the conflicting files to the trash
+   KeepOriginal,
+
+   /// take the most recently modified file of the conflict
+   TakeNewest
+}
+
+impl Display for Policy
+{
+   fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+   {
+      match *self {
+         Policy::Notify       => write!(f, "notify"),
+         Policy::Queue        => write!(f, "queue"),
+         Policy::KeepOriginal => write!(f, "keep-original"),
+         Policy::TakeNewest   => write!(f, "take-newest")
+      }
+   }
+}
+
+/// Parses a policy in the form it's displayed, e.g. `keep-original`.
+pub fn parse_policy(input: &str) -> Option<Policy>
+{
+   [Policy::Notify, Policy::Queue, Policy::KeepOriginal, Policy::TakeNewest].iter()
+      .find(|p| p.to_string() == input)
+      .cloned()
+}
+
+// The queue of conflicts found by the watch mode for the next interactive session
+// is the file `queue` inside of the data directory of confsolve, with one line per
+// conflict in the form:
+//
+//    `<conflict type>\t<path of the original file>`
+//
+// The queue is only appended to by `enqueue`. `take_queued` renames it before reading
+// it and appends the lines it doesn't take again, so that no line appended by a
+// concurrently running watch mode is lost.
+
+/// Appends the conflict of type `conf_type` of the original file `orig_file` to the queue.
+pub fn enqueue(conf_type: ConflictType, orig_file: &Path) -> AppResult<()>
+{
+   let queue = queue_file()?;
+   if let Some(dir) = queue.parent() {
+      create_dir_all(dir).with_path(dir)?;
+   }
+
+   let mut handle = OpenOptions::new().create(true).append(true).open(&queue).with_path(&queue)?;
+   writeln!(handle, "{}\t{}", conf_type, orig_file.display()).with_path(&queue)?;
+   Ok(())
+}
+
+/// Removes the queued conflicts of type `conf_type` inside of `start_dir` from
+/// the queue and returns their original files, in the order they've been queued.
+pub fn take_queued(conf_type: ConflictType, start_dir: &Path) -> AppResult<Vec<PathBuf>>
+{
+   let queue = queue_file()?;
+   let taken_queue = queue.with_file_name(format!("queue-{}", random_suffix()));
+   match fs::rename(&queue, &taken_queue) {
+      Ok(())                                                => (),
+      Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(err)                                              => return Err(err).with_path(&queue)
+   }
+
+   let conf_type = conf_type.to_string();
+   let mut taken = Vec::new();
+   let mut kept = String::new();
+   for line in fs::read_to_string(&taken_queue).with_path(&taken_queue)?.lines() {
+      let mut fields = line.splitn(2, '\t');
+      let (queued_type, orig_file) = (fields.next().unwrap_or(""), PathBuf::from(fields.next().unwrap_or("")));
+      if queued_type == conf_type && orig_file.starts_with(start_dir) {
+         if ! taken.contains(&orig_file) {
+            taken.push(orig_file);
+         }
+      }
+      else if ! line.is_empty() {
+         kept.push_str(line);
+         kept.push('\n');
+      }
+   }
+
+   if ! kept.is_empty() {
+      let mut handle = OpenOptions::new().create(true).append(true).open(&queue).with_path(&queue)?;
+      handle.write_all(kept.as_bytes()).with_path(&queue)?;
+   }
+
+   fs::remove_file(&taken_queue).with_path(&taken_queue)?;
+   Ok(taken)
+}
+
+fn queue_file() -> AppResult<PathBuf>
+{
+   appdirs::data_home()
+      .map(|dir| dir.join("confsolve").join("queue"))
+      .ok_or(AppError::from_string("Couldn't get data directory!".to_string()))
+}
+
+#[test]
+fn tests()
+{
+   for policy in [Policy::Notify, Policy::Queue, Policy::KeepOriginal, Policy::TakeNewest] {
+      assert_eq!(parse_policy(&policy.to_string()), Some(policy));
+   }
+
+   assert_eq!(parse_policy("blub"), None);
+}